@@ -0,0 +1,35 @@
+//! Basic usage of the logging engine: stdout logging plus a rotated file log
+
+use logging_engine::{FileTransportConfig, FsyncPolicy, TransportKind, UltraLogger};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Stdout transport
+    let logger = UltraLogger::new("trading-system".to_string());
+    logger.info("System started".to_string()).await?;
+    logger
+        .warn("Market volatility detected".to_string())
+        .await?;
+    logger.shutdown().await?;
+
+    // File transport with size-based rotation
+    let file_logger = UltraLogger::builder()
+        .with_service("order-gateway")
+        .with_transport(TransportKind::File(FileTransportConfig {
+            path: std::env::temp_dir().join("logging-engine/orders.log"),
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 3,
+            fsync_policy: FsyncPolicy::OnRotate,
+        }))
+        .build()?;
+
+    for order_id in 0..10 {
+        file_logger
+            .info(format!("Order {} received", order_id))
+            .await?;
+    }
+    file_logger.shutdown().await?;
+
+    println!("{:?}", file_logger.stats());
+    Ok(())
+}
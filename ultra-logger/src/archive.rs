@@ -0,0 +1,229 @@
+//! Compressed archival of batches for long-term retention
+//!
+//! Entries are written as zstd-compressed NDJSON parts laid out like object
+//! keys, one open part per service and hour:
+//!
+//! ```text
+//! <root>/<service>/<YYYY-MM-DD>/<HH>/part-<N>.jsonl.zst
+//! ```
+//!
+//! Date and hour come from each entry's time. A part is written as
+//! `part-<N>.jsonl.zst.partial` and renamed once complete, so anything with
+//! the final name is a whole zstd frame that can be shipped to a bucket as
+//! is. Parts complete when they reach `part_size` uncompressed bytes, when
+//! they have been open for `roll_interval` (checked as batches arrive), and
+//! on shutdown.
+
+use crate::config::TimestampSource;
+use crate::error::Result;
+use crate::transport::Transport;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const PART_SUFFIX: &str = ".jsonl.zst";
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Archive configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveConfig {
+    /// Directory the object layout is created under
+    pub root: PathBuf,
+
+    /// Longest a part stays open before it is completed
+    pub roll_interval: Duration,
+
+    /// Uncompressed bytes after which a part is completed
+    pub part_size: u64,
+
+    /// zstd compression level
+    pub compression_level: i32,
+
+    /// Time that picks an entry's date and hour
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("archive"),
+            roll_interval: Duration::from_secs(10 * 60),
+            part_size: 128 * 1024 * 1024,
+            compression_level: 3,
+            timestamp_source: TimestampSource::default(),
+        }
+    }
+}
+
+/// Part currently being written
+struct Part {
+    encoder: zstd::Encoder<'static, File>,
+    path: PathBuf,
+    written: u64,
+    opened: Instant,
+}
+
+impl Part {
+    /// Open the next unused part number in `dir`
+    fn open(dir: &Path, level: i32) -> Result<Self> {
+        let path = next_part_path(dir, PART_SUFFIX)?;
+        let file = File::create(partial_path(&path))?;
+        Ok(Self {
+            encoder: zstd::Encoder::new(file, level)?,
+            path,
+            written: 0,
+            opened: Instant::now(),
+        })
+    }
+
+    /// Finish the zstd frame, sync, and give the part its final name
+    fn complete(self) -> Result<()> {
+        let file = self.encoder.finish()?;
+        file.sync_all()?;
+        fs::rename(partial_path(&self.path), &self.path)?;
+        Ok(())
+    }
+}
+
+/// `dir/part-<N><suffix>` for the first `N` above every part already in
+/// `dir`, complete or not; creates `dir`
+pub(crate) fn next_part_path(dir: &Path, suffix: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let mut next = 0;
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let number = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("part-"))
+            .and_then(|rest| rest.split('.').next())
+            .and_then(|n| n.parse::<u64>().ok());
+        if let Some(number) = number {
+            next = next.max(number + 1);
+        }
+    }
+    Ok(dir.join(format!("part-{}{}", next, suffix)))
+}
+
+/// Where a part is written until it is complete
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial)
+}
+
+/// `name` made safe as one path component: no separators, no escaping the
+/// parent
+pub(crate) fn path_segment(name: &str) -> String {
+    let segment: String = name
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '_' | '-' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    match segment.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => segment,
+    }
+}
+
+/// Fields needed to place an entry in the layout
+#[derive(Deserialize)]
+struct Placement {
+    service: String,
+    timestamp: DateTime<Utc>,
+    #[serde(default)]
+    event_time: Option<DateTime<Utc>>,
+}
+
+/// Rolls batches into compressed part files for archival
+pub struct ArchiveTransport {
+    config: ArchiveConfig,
+    parts: BTreeMap<PathBuf, Part>,
+}
+
+impl ArchiveTransport {
+    pub fn new(config: ArchiveConfig) -> Self {
+        Self {
+            config,
+            parts: BTreeMap::new(),
+        }
+    }
+
+    fn directory(&self, placement: &Placement) -> PathBuf {
+        let time = match self.config.timestamp_source {
+            TimestampSource::Logged => placement.timestamp,
+            TimestampSource::Event => placement.event_time.unwrap_or(placement.timestamp),
+        };
+        self.config
+            .root
+            .join(path_segment(&placement.service))
+            .join(time.format("%Y-%m-%d").to_string())
+            .join(time.format("%H").to_string())
+    }
+
+    /// Complete every part that matches `done`
+    fn complete_where(&mut self, done: impl Fn(&Part) -> bool) -> Result<()> {
+        let finished: Vec<PathBuf> = self
+            .parts
+            .iter()
+            .filter(|(_, part)| done(part))
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        for dir in finished {
+            if let Some(part) = self.parts.remove(&dir) {
+                part.complete()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for ArchiveTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        let batch = &*crate::to_ndjson(batch)?;
+        let mut by_directory: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+        for line in batch.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            let placement: Placement = serde_json::from_slice(line)?;
+            let lines = by_directory.entry(self.directory(&placement)).or_default();
+            lines.extend_from_slice(line);
+            lines.push(b'\n');
+        }
+
+        for (dir, lines) in by_directory {
+            let mut part = match self.parts.remove(&dir) {
+                Some(part) => part,
+                None => Part::open(&dir, self.config.compression_level)?,
+            };
+            part.encoder.write_all(&lines)?;
+            part.written += lines.len() as u64;
+            if part.written >= self.config.part_size {
+                part.complete()?;
+            } else {
+                self.parts.insert(dir, part);
+            }
+        }
+
+        let roll_interval = self.config.roll_interval;
+        self.complete_where(|part| part.opened.elapsed() >= roll_interval)
+    }
+
+    /// Push compressed data to the open part files without completing them
+    async fn flush(&mut self) -> Result<()> {
+        for part in self.parts.values_mut() {
+            part.encoder.flush()?;
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.complete_where(|_| true)
+    }
+}
@@ -0,0 +1,403 @@
+//! Wall-clock jump detection and compensation
+//!
+//! Entry timestamps come from the wall clock, which an NTP step can move by
+//! seconds in either direction between two entries, leaving latencies
+//! computed across the step wrong or negative. The guard tracks the wall
+//! clock's offset from a monotonic clock and treats any change in it larger
+//! than the tolerance as a jump (smaller changes, such as NTP slewing, are
+//! followed). The next entry logged carries `clock_jump_ms`, the size of the
+//! jump.
+//!
+//! With compensation on, a jump is worked off gradually instead, the way NTP
+//! slews: timestamps carry on from the old timeline and converge on the new
+//! wall clock at `slew_rate`, so they never go backwards and intervals stay
+//! within `slew_rate` of true. Entries logged meanwhile carry
+//! `clock_adjust_ms`, the correction applied to their timestamp.
+//!
+//! Timestamps are UTC, so DST transitions don't move them; sinks that render
+//! local time apply DST themselves.
+
+use crate::config::ClockGuardConfig;
+use crate::StatsCounters;
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time source read by the clock guard
+pub trait Clock: Send + Sync {
+    /// Current wall-clock time
+    fn wall(&self) -> DateTime<Utc>;
+
+    /// Time elapsed since a fixed origin on a clock that never jumps
+    fn monotonic(&self) -> Duration;
+}
+
+/// The system clocks: [`Utc::now`] and [`Instant`]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn wall(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Hand-driven clock for testing behaviour across clock steps
+///
+/// Time only moves when told to: [`advance`](SimulatedClock::advance) moves
+/// both clocks as real time passing would, [`step`](SimulatedClock::step)
+/// moves only the wall clock, as an NTP step does.
+///
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// # use async_trait::async_trait;
+/// # use ultra_logger::{Result, Transport};
+/// # #[derive(Clone, Default)]
+/// # struct Capture(Arc<Mutex<Vec<u8>>>);
+/// # #[async_trait]
+/// # impl Transport for Capture {
+/// #     async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+/// #         self.0.lock().unwrap().extend_from_slice(batch);
+/// #         Ok(())
+/// #     }
+/// # }
+/// use std::time::Duration;
+/// use ultra_logger::{ClockGuardConfig, LogEntry, SimulatedClock, UltraLogger};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<()> {
+/// # let capture = Capture::default();
+/// let clock = Arc::new(SimulatedClock::new("2026-03-08T06:59:59Z".parse().unwrap()));
+/// let logger = UltraLogger::builder()
+///     .with_clock(clock.clone())
+///     .with_clock_guard(ClockGuardConfig::default())
+///     .with_custom_transport(Box::new(capture.clone()))
+///     .build()?;
+///
+/// logger.info("before".into()).await?;
+/// clock.advance(Duration::from_millis(10));
+/// clock.step(chrono::Duration::seconds(-2)); // nightly sync steps back 2s
+/// logger.info("after".into()).await?;
+/// clock.advance(Duration::from_secs(30));
+/// logger.info("slewed out".into()).await?;
+/// logger.shutdown().await?;
+///
+/// let output = capture.0.lock().unwrap().clone();
+/// let entries: Vec<LogEntry> = serde_json::Deserializer::from_slice(&output)
+///     .into_iter()
+///     .collect::<std::result::Result<_, _>>()?;
+/// // Still 10ms apart, not 2s backwards
+/// assert_eq!((entries[1].timestamp - entries[0].timestamp).num_milliseconds(), 10);
+/// assert!(entries[1].fields.contains_key("clock_jump_ms"));
+/// assert!(!entries[2].fields.contains_key("clock_adjust_ms"));
+/// assert_eq!(logger.stats().clock_jumps, 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct SimulatedClock {
+    /// Wall time and monotonic time
+    now: Mutex<(DateTime<Utc>, Duration)>,
+}
+
+impl SimulatedClock {
+    /// Clock reading `start`, with the monotonic clock at zero
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new((start, Duration::ZERO)),
+        }
+    }
+
+    /// Let `elapsed` pass on both clocks
+    pub fn advance(&self, elapsed: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|e| e.into_inner());
+        now.0 += elapsed;
+        now.1 += elapsed;
+    }
+
+    /// Move the wall clock alone by `delta`, which may be negative
+    pub fn step(&self, delta: chrono::Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|e| e.into_inner());
+        now.0 += delta;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn wall(&self) -> DateTime<Utc> {
+        self.now.lock().unwrap_or_else(|e| e.into_inner()).0
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.now.lock().unwrap_or_else(|e| e.into_inner()).1
+    }
+}
+
+/// Nanoseconds since the epoch, saturating outside 1677–2262
+pub(crate) fn unix_nanos(time: DateTime<Utc>) -> i64 {
+    match time.timestamp_nanos_opt() {
+        Some(nanos) => nanos,
+        None if time.timestamp() < 0 => i64::MIN,
+        None => i64::MAX,
+    }
+}
+
+/// Timestamp from the guard, with what to annotate the entry with
+pub(crate) struct Stamp {
+    pub(crate) time: DateTime<Utc>,
+    /// Jump detected since the previous entry, in nanoseconds
+    pub(crate) jump: Option<i64>,
+    /// Correction applied to `time`, in nanoseconds
+    pub(crate) adjust: Option<i64>,
+}
+
+/// Correction being worked off after a compensated jump
+struct Slew {
+    /// Correction at `since`, in nanoseconds
+    correction: i64,
+    /// Monotonic time the correction was set, in nanoseconds
+    since: i64,
+}
+
+pub(crate) struct ClockGuard {
+    clock: Arc<dyn Clock>,
+    tolerance: i64,
+    compensate: bool,
+    slew_rate: f64,
+    /// Wall clock minus monotonic clock as last observed, in nanoseconds
+    offset: AtomicI64,
+    /// Detected jump not yet reported on an entry
+    pending_jump: AtomicI64,
+    slewing: AtomicBool,
+    slew: Mutex<Option<Slew>>,
+    stats: Arc<StatsCounters>,
+}
+
+impl ClockGuard {
+    pub(crate) fn new(
+        config: ClockGuardConfig,
+        clock: Arc<dyn Clock>,
+        stats: Arc<StatsCounters>,
+    ) -> Self {
+        let offset = unix_nanos(clock.wall()) - clock.monotonic().as_nanos() as i64;
+        Self {
+            clock,
+            tolerance: config.tolerance.as_nanos() as i64,
+            compensate: config.compensate,
+            slew_rate: config.slew_rate.clamp(f64::EPSILON, 0.5),
+            offset: AtomicI64::new(offset),
+            pending_jump: AtomicI64::new(0),
+            slewing: AtomicBool::new(false),
+            slew: Mutex::new(None),
+            stats,
+        }
+    }
+
+    /// Timestamp for an entry logged now, taking any reported jump
+    pub(crate) fn stamp(&self) -> Stamp {
+        let (time, adjust) = self.read();
+        let jump = self.pending_jump.swap(0, Ordering::Relaxed);
+        Stamp {
+            time,
+            jump: (jump != 0).then_some(jump),
+            adjust,
+        }
+    }
+
+    /// Current time, for measuring against entry timestamps
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.read().0
+    }
+
+    fn read(&self) -> (DateTime<Utc>, Option<i64>) {
+        let monotonic = self.clock.monotonic().as_nanos() as i64;
+        let wall = self.clock.wall();
+        let observed = unix_nanos(wall) - monotonic;
+
+        let drift = observed - self.offset.load(Ordering::Relaxed);
+        if drift.abs() > self.tolerance {
+            self.jumped(observed, monotonic);
+        } else if drift.abs() > self.tolerance / 4 {
+            // Follow gradual adjustment so it never adds up to a false jump
+            self.offset.store(observed, Ordering::Relaxed);
+        }
+
+        if !self.slewing.load(Ordering::Acquire) {
+            return (wall, None);
+        }
+        let mut slew = self.slew.lock().unwrap_or_else(|e| e.into_inner());
+        let correction = slew
+            .as_ref()
+            .map_or(0, |slew| self.remaining(slew, monotonic));
+        if correction == 0 {
+            *slew = None;
+            self.slewing.store(false, Ordering::Release);
+            return (wall, None);
+        }
+        (
+            wall + chrono::Duration::nanoseconds(correction),
+            Some(correction),
+        )
+    }
+
+    /// Record a jump to `observed`, unless another caller already has
+    fn jumped(&self, observed: i64, monotonic: i64) {
+        let mut slew = self.slew.lock().unwrap_or_else(|e| e.into_inner());
+        let drift = observed - self.offset.load(Ordering::Relaxed);
+        if drift.abs() <= self.tolerance {
+            return;
+        }
+        self.offset.store(observed, Ordering::Relaxed);
+        self.pending_jump.fetch_add(drift, Ordering::Relaxed);
+        self.stats.clock_jumps.fetch_add(1, Ordering::Relaxed);
+
+        if self.compensate {
+            let carried = slew
+                .as_ref()
+                .map_or(0, |slew| self.remaining(slew, monotonic));
+            *slew = Some(Slew {
+                correction: carried - drift,
+                since: monotonic,
+            });
+            self.slewing.store(true, Ordering::Release);
+        }
+    }
+
+    /// Correction left at `monotonic`
+    fn remaining(&self, slew: &Slew, monotonic: i64) -> i64 {
+        let worked_off = ((monotonic - slew.since) as f64 * self.slew_rate) as i64;
+        let left = slew.correction.abs().saturating_sub(worked_off).max(0);
+        left * slew.correction.signum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: i64 = 1_000_000;
+
+    fn guard(compensate: bool) -> (Arc<SimulatedClock>, ClockGuard, Arc<StatsCounters>) {
+        let clock = Arc::new(SimulatedClock::new("2026-03-08T06:59:59Z".parse().unwrap()));
+        let stats = Arc::new(StatsCounters::default());
+        let config = ClockGuardConfig {
+            compensate,
+            ..ClockGuardConfig::default()
+        };
+        let guard = ClockGuard::new(config, clock.clone(), stats.clone());
+        (clock, guard, stats)
+    }
+
+    fn jumps(stats: &StatsCounters) -> u64 {
+        stats.clock_jumps.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn forward_jump_is_reported_and_held_back() {
+        let (clock, guard, stats) = guard(true);
+        let before = guard.stamp().time;
+        clock.advance(Duration::from_millis(10));
+        clock.step(chrono::Duration::seconds(2));
+
+        let stamp = guard.stamp();
+        assert_eq!(stamp.jump, Some(2_000 * MS));
+        assert_eq!(stamp.adjust, Some(-2_000 * MS));
+        assert_eq!((stamp.time - before).num_milliseconds(), 10);
+        assert_eq!(jumps(&stats), 1);
+
+        // Reported once
+        assert_eq!(guard.stamp().jump, None);
+    }
+
+    #[test]
+    fn uncompensated_jump_is_only_annotated() {
+        let (clock, guard, stats) = guard(false);
+        guard.stamp();
+        clock.step(chrono::Duration::seconds(-2));
+
+        let stamp = guard.stamp();
+        assert_eq!(stamp.jump, Some(-2_000 * MS));
+        assert_eq!(stamp.adjust, None);
+        assert_eq!(stamp.time, clock.wall());
+        assert_eq!(jumps(&stats), 1);
+
+        let next = guard.stamp();
+        assert_eq!((next.jump, next.adjust), (None, None));
+    }
+
+    #[test]
+    fn back_to_back_jumps_add_up() {
+        let (clock, guard, stats) = guard(true);
+        let before = guard.stamp().time;
+        clock.step(chrono::Duration::seconds(-1));
+        let first = guard.stamp();
+        clock.step(chrono::Duration::seconds(-1));
+        let second = guard.stamp();
+
+        assert_eq!(first.jump, Some(-1_000 * MS));
+        assert_eq!(second.jump, Some(-1_000 * MS));
+        assert_eq!(second.adjust, Some(2_000 * MS));
+        assert_eq!(first.time, before);
+        assert_eq!(second.time, before);
+        assert_eq!(jumps(&stats), 2);
+    }
+
+    #[test]
+    fn drift_within_tolerance_is_followed() {
+        let (clock, guard, stats) = guard(true);
+        guard.stamp();
+        // Two 50ms corrections add up to more than the 100ms tolerance, but
+        // each is followed before the next
+        for _ in 0..2 {
+            clock.step(chrono::Duration::milliseconds(50));
+            let stamp = guard.stamp();
+            assert_eq!((stamp.jump, stamp.adjust), (None, None));
+            assert_eq!(stamp.time, clock.wall());
+        }
+        assert_eq!(jumps(&stats), 0);
+    }
+
+    #[test]
+    fn slew_converges_without_going_backwards() {
+        let (clock, guard, _stats) = guard(true);
+        guard.stamp();
+        clock.step(chrono::Duration::seconds(-2));
+        let mut last = guard.stamp();
+        assert_eq!(last.adjust, Some(2_000 * MS));
+
+        // 2s at the default slew rate of 0.1 takes 20s
+        for tick in 1..=200 {
+            clock.advance(Duration::from_millis(100));
+            let stamp = guard.stamp();
+            assert_eq!(
+                (stamp.time - last.time).num_milliseconds(),
+                90,
+                "tick {tick}"
+            );
+            if tick < 200 {
+                assert_eq!(stamp.adjust, Some((2_000 - 10 * tick) * MS));
+            } else {
+                assert_eq!(stamp.adjust, None);
+                assert_eq!(stamp.time, clock.wall());
+            }
+            last = stamp;
+        }
+
+        clock.advance(Duration::from_millis(100));
+        let stamp = guard.stamp();
+        assert_eq!(stamp.adjust, None);
+        assert_eq!((stamp.time - last.time).num_milliseconds(), 100);
+    }
+}
@@ -0,0 +1,260 @@
+//! Parquet output for analytics (`parquet` feature)
+//!
+//! Entries become Arrow record batches written to rolling Parquet files,
+//! partitioned Hive-style so DuckDB and Spark pick the partitions up as
+//! columns:
+//!
+//! ```text
+//! <root>/date=<YYYY-MM-DD>/service=<service>/part-<N>.parquet
+//! ```
+//!
+//! The core entry fields are typed columns; structured `fields` are kept as
+//! one JSON object column, since their keys and types vary per entry. As
+//! with the archive, a file is written as `.partial` and renamed once its
+//! footer is written: when it reaches `max_rows`, when it has been open for
+//! `roll_interval` (checked as batches arrive), and on shutdown.
+
+use crate::archive::{next_part_path, partial_path, path_segment};
+use crate::config::TimestampSource;
+use crate::error::{LoggerError, Result};
+use crate::transport::Transport;
+use crate::{LogEntry, LogLevel};
+use arrow_array::builder::{StringBuilder, TimestampNanosecondBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PART_SUFFIX: &str = ".parquet";
+
+/// Parquet output configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParquetConfig {
+    /// Directory the partitions are created under
+    pub root: PathBuf,
+
+    /// Longest a file stays open before it is completed
+    pub roll_interval: Duration,
+
+    /// Rows after which a file is completed
+    pub max_rows: usize,
+
+    /// Rows buffered in memory per row group
+    pub row_group_size: usize,
+
+    /// zstd compression level for column chunks
+    pub compression_level: i32,
+
+    /// Time that picks an entry's date partition
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+}
+
+impl Default for ParquetConfig {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("parquet"),
+            roll_interval: Duration::from_secs(10 * 60),
+            max_rows: 1_000_000,
+            row_group_size: 64 * 1024,
+            compression_level: 3,
+            timestamp_source: TimestampSource::default(),
+        }
+    }
+}
+
+/// File currently being written
+struct Part {
+    writer: ArrowWriter<File>,
+    path: PathBuf,
+    rows: usize,
+    opened: Instant,
+}
+
+impl Part {
+    /// Write the footer, sync, and give the file its final name
+    fn complete(self) -> Result<()> {
+        let file = self.writer.into_inner().map_err(parquet_error)?;
+        file.sync_all()?;
+        fs::rename(partial_path(&self.path), &self.path)?;
+        Ok(())
+    }
+}
+
+fn parquet_error(error: impl std::fmt::Display) -> LoggerError {
+    LoggerError::Transport(format!("parquet: {}", error))
+}
+
+/// Writes batches as Hive-partitioned Parquet files
+pub struct ParquetTransport {
+    config: ParquetConfig,
+    schema: SchemaRef,
+    properties: WriterProperties,
+    parts: BTreeMap<PathBuf, Part>,
+}
+
+impl ParquetTransport {
+    pub fn new(config: ParquetConfig) -> Result<Self> {
+        let timestamp = DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into()));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("timestamp", timestamp.clone(), false),
+            Field::new("event_time", timestamp, true),
+            Field::new("level", DataType::Utf8, false),
+            Field::new("service", DataType::Utf8, false),
+            Field::new("message", DataType::Utf8, false),
+            Field::new("event_id", DataType::Utf8, true),
+            Field::new("error_code", DataType::Utf8, true),
+            Field::new("fields", DataType::Utf8, true),
+        ]));
+        let level = ZstdLevel::try_new(config.compression_level).map_err(parquet_error)?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(level))
+            .set_max_row_group_size(config.row_group_size.max(1))
+            .build();
+
+        Ok(Self {
+            config,
+            schema,
+            properties,
+            parts: BTreeMap::new(),
+        })
+    }
+
+    fn directory(&self, entry: &LogEntry) -> PathBuf {
+        let date = entry.time(self.config.timestamp_source).format("%Y-%m-%d");
+        self.config
+            .root
+            .join(format!("date={}", date))
+            .join(format!("service={}", path_segment(&entry.service)))
+    }
+
+    fn record_batch(&self, entries: &[LogEntry]) -> Result<RecordBatch> {
+        let nanos = |time: DateTime<Utc>| {
+            time.timestamp_nanos_opt()
+                .ok_or_else(|| parquet_error(format!("time out of range: {}", time)))
+        };
+        let mut timestamp = TimestampNanosecondBuilder::new().with_timezone("UTC");
+        let mut event_time = TimestampNanosecondBuilder::new().with_timezone("UTC");
+        let mut level = StringBuilder::new();
+        let mut service = StringBuilder::new();
+        let mut message = StringBuilder::new();
+        let mut event_id = StringBuilder::new();
+        let mut error_code = StringBuilder::new();
+        let mut fields = StringBuilder::new();
+        for entry in entries {
+            timestamp.append_value(nanos(entry.timestamp)?);
+            event_time.append_option(entry.event_time.map(nanos).transpose()?);
+            level.append_value(match entry.level {
+                LogLevel::Debug => "DEBUG",
+                LogLevel::Info => "INFO",
+                LogLevel::Warn => "WARN",
+                LogLevel::Error => "ERROR",
+            });
+            service.append_value(&entry.service);
+            message.append_value(&entry.message);
+            event_id.append_option(entry.event_id.as_deref());
+            error_code.append_option(entry.error_code.as_deref());
+            if entry.fields.is_empty() {
+                fields.append_null();
+            } else {
+                fields.append_value(serde_json::to_string(&entry.fields)?);
+            }
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(timestamp.finish()),
+            Arc::new(event_time.finish()),
+            Arc::new(level.finish()),
+            Arc::new(service.finish()),
+            Arc::new(message.finish()),
+            Arc::new(event_id.finish()),
+            Arc::new(error_code.finish()),
+            Arc::new(fields.finish()),
+        ];
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(parquet_error)
+    }
+
+    fn open(&self, dir: &Path) -> Result<Part> {
+        let path = next_part_path(dir, PART_SUFFIX)?;
+        let file = File::create(partial_path(&path))?;
+        let writer = ArrowWriter::try_new(file, self.schema.clone(), Some(self.properties.clone()))
+            .map_err(parquet_error)?;
+        Ok(Part {
+            writer,
+            path,
+            rows: 0,
+            opened: Instant::now(),
+        })
+    }
+
+    /// Complete every part that matches `done`
+    fn complete_where(&mut self, done: impl Fn(&Part) -> bool) -> Result<()> {
+        let finished: Vec<PathBuf> = self
+            .parts
+            .iter()
+            .filter(|(_, part)| done(part))
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        for dir in finished {
+            if let Some(part) = self.parts.remove(&dir) {
+                part.complete()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for ParquetTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        let batch = &*crate::to_ndjson(batch)?;
+        let mut by_directory: BTreeMap<PathBuf, Vec<LogEntry>> = BTreeMap::new();
+        for entry in serde_json::Deserializer::from_slice(batch).into_iter::<LogEntry>() {
+            let entry = entry?;
+            by_directory
+                .entry(self.directory(&entry))
+                .or_default()
+                .push(entry);
+        }
+
+        for (dir, entries) in by_directory {
+            let records = self.record_batch(&entries)?;
+            let mut part = match self.parts.remove(&dir) {
+                Some(part) => part,
+                None => self.open(&dir)?,
+            };
+            part.writer.write(&records).map_err(parquet_error)?;
+            part.rows += entries.len();
+            if part.rows >= self.config.max_rows {
+                part.complete()?;
+            } else {
+                self.parts.insert(dir, part);
+            }
+        }
+
+        let roll_interval = self.config.roll_interval;
+        self.complete_where(|part| part.opened.elapsed() >= roll_interval)
+    }
+
+    /// Close the open row groups so their data is on disk; files stay
+    /// unreadable until completed, as the footer comes last
+    async fn flush(&mut self) -> Result<()> {
+        for part in self.parts.values_mut() {
+            part.writer.flush().map_err(parquet_error)?;
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.complete_where(|_| true)
+    }
+}
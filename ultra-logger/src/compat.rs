@@ -0,0 +1,127 @@
+//! Shims for services still written against the 0.x API
+//!
+//! Each item is deprecated and delegates to the current API, so a service
+//! can migrate one call site at a time over several releases; the
+//! deprecation note says what to use instead. New options only ever land
+//! on the builder, not here.
+
+use crate::config::LoggerConfig;
+use crate::error::Result;
+use crate::{LogLevel, LogValue, UltraLogger};
+
+/// 0.x `UltraLogger::new(config).await`
+///
+/// ```no_run
+/// # async fn run() -> ultra_logger::Result<()> {
+/// #[allow(deprecated)]
+/// let logger = ultra_logger::compat::new(ultra_logger::LoggerConfig::default()).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[deprecated(note = "use `UltraLogger::builder().with_config(&config)?.build()`")]
+pub async fn new(config: LoggerConfig) -> Result<UltraLogger> {
+    UltraLogger::builder().with_config(&config)?.build()
+}
+
+/// 0.x `logger.debug(message, &[("key", "value")]).await`
+#[deprecated(note = "use `debug_kv!(logger, message, key = value)` or `log_with_fields`")]
+pub async fn debug(logger: &UltraLogger, message: &str, fields: &[(&str, &str)]) -> Result<()> {
+    log_with_str_fields(logger, LogLevel::Debug, message, fields).await
+}
+
+/// 0.x `logger.info(message, &[("key", "value")]).await`
+///
+/// ```
+/// # #[tokio::main]
+/// # async fn main() -> ultra_logger::Result<()> {
+/// # let logger = ultra_logger::UltraLogger::new("order-gateway".to_string());
+/// #[allow(deprecated)]
+/// ultra_logger::compat::info(&logger, "Order received", &[("symbol", "BTCUSD")]).await?;
+/// # logger.shutdown().await
+/// # }
+/// ```
+#[deprecated(note = "use `info_kv!(logger, message, key = value)` or `log_with_fields`")]
+pub async fn info(logger: &UltraLogger, message: &str, fields: &[(&str, &str)]) -> Result<()> {
+    log_with_str_fields(logger, LogLevel::Info, message, fields).await
+}
+
+/// 0.x `logger.warn(message, &[("key", "value")]).await`
+#[deprecated(note = "use `warn_kv!(logger, message, key = value)` or `log_with_fields`")]
+pub async fn warn(logger: &UltraLogger, message: &str, fields: &[(&str, &str)]) -> Result<()> {
+    log_with_str_fields(logger, LogLevel::Warn, message, fields).await
+}
+
+/// 0.x `logger.error(message, &[("key", "value")]).await`
+#[deprecated(note = "use `error_kv!(logger, message, key = value)` or `log_with_fields`")]
+pub async fn error(logger: &UltraLogger, message: &str, fields: &[(&str, &str)]) -> Result<()> {
+    log_with_str_fields(logger, LogLevel::Error, message, fields).await
+}
+
+/// 0.x fields were all strings; they become string values
+async fn log_with_str_fields(
+    logger: &UltraLogger,
+    level: LogLevel,
+    message: &str,
+    fields: &[(&str, &str)],
+) -> Result<()> {
+    let fields: Vec<(&str, LogValue)> = fields
+        .iter()
+        .map(|(key, value)| (*key, LogValue::from(*value)))
+        .collect();
+    logger
+        .log_with_fields(level, message.to_string(), &fields)
+        .await
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use super::*;
+    use crate::{LogEntry, Transport};
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    #[async_trait]
+    impl Transport for Capture {
+        async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+            self.0.lock().unwrap().extend_from_slice(batch);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn shims_deliver_string_fields_at_their_level() {
+        let capture = Capture::default();
+        let logger = UltraLogger::builder()
+            .with_level(LogLevel::Debug)
+            .with_custom_transport(Box::new(capture.clone()))
+            .build()
+            .unwrap();
+        debug(
+            &logger,
+            "tick_received",
+            &[("symbol", "BTCUSD"), ("bid", "45000.5")],
+        )
+        .await
+        .unwrap();
+        error(&logger, "order_rejected", &[]).await.unwrap();
+        logger.shutdown().await.unwrap();
+
+        let output = capture.0.lock().unwrap().clone();
+        let entries: Vec<LogEntry> = serde_json::Deserializer::from_slice(&output)
+            .into_iter()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].level, LogLevel::Debug);
+        assert_eq!(entries[0].message, "tick_received");
+        assert_eq!(entries[0].fields["symbol"], LogValue::from("BTCUSD"));
+        // Still a string, as in 0.x
+        assert_eq!(entries[0].fields["bid"], LogValue::from("45000.5"));
+        assert_eq!(entries[1].level, LogLevel::Error);
+        assert!(entries[1].fields.is_empty());
+    }
+}
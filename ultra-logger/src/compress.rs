@@ -0,0 +1,140 @@
+//! Batch compression between serialization and the transport
+//!
+//! Every algorithm writes its standard self-describing frame format, so
+//! a compressed batch starts with that format's magic number, never with
+//! the `{` of an NDJSON batch. [`decompress`] uses this to accept either,
+//! and the command-line tools (`zstd -d`, `lz4 -d`, `snzip -d`) read the
+//! frames too.
+
+use crate::config::{CompressionAlgorithm, CompressionConfig};
+use crate::error::{LoggerError, Result};
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+const SNAPPY_MAGIC: [u8; 10] = [0xff, 0x06, 0x00, 0x00, b's', b'N', b'a', b'P', b'p', b'Y'];
+
+/// Compression stage owned by the background processor
+pub(crate) struct Compressor {
+    config: CompressionConfig,
+}
+
+impl Compressor {
+    /// `None` when the configuration turns compression off
+    pub(crate) fn new(config: CompressionConfig) -> Option<Self> {
+        match config.algorithm {
+            CompressionAlgorithm::None => None,
+            _ => Some(Self { config }),
+        }
+    }
+
+    /// Compress `input` into `out` (replacing its contents); `false` when
+    /// the batch is under the size threshold and was left alone
+    pub(crate) fn compress(&self, input: &[u8], out: &mut Vec<u8>) -> io::Result<bool> {
+        if input.len() < self.config.min_size {
+            return Ok(false);
+        }
+        out.clear();
+        match self.config.algorithm {
+            CompressionAlgorithm::None => return Ok(false),
+            CompressionAlgorithm::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(out);
+                encoder.write_all(input)?;
+                encoder.finish().map_err(io::Error::other)?;
+            }
+            CompressionAlgorithm::Zstd => {
+                zstd::stream::copy_encode(input, &mut *out, self.config.level)?;
+            }
+            CompressionAlgorithm::Snappy => {
+                let mut encoder = snap::write::FrameEncoder::new(out);
+                encoder.write_all(input)?;
+                encoder.flush()?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Undo the logger's batch compression, if any
+///
+/// For readers on the other end of a byte-oriented transport: a batch in
+/// any supported frame format is decompressed, anything else (a batch under
+/// the size threshold, or from an uncompressed logger) is returned as is.
+pub fn decompress(batch: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let mut out = Vec::new();
+    let read = if batch.starts_with(&ZSTD_MAGIC) {
+        zstd::stream::copy_decode(batch, &mut out)
+    } else if batch.starts_with(&LZ4_MAGIC) {
+        lz4_flex::frame::FrameDecoder::new(batch)
+            .read_to_end(&mut out)
+            .map(drop)
+    } else if batch.starts_with(&SNAPPY_MAGIC) {
+        snap::read::FrameDecoder::new(batch)
+            .read_to_end(&mut out)
+            .map(drop)
+    } else {
+        return Ok(Cow::Borrowed(batch));
+    };
+
+    read.map_err(|e| LoggerError::Transport(format!("corrupt compressed batch: {}", e)))?;
+    Ok(Cow::Owned(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A few kilobytes of NDJSON-like text
+    fn batch() -> Vec<u8> {
+        (0..100)
+            .flat_map(|i| {
+                format!(
+                    "{{\"level\":\"INFO\",\"service\":\"order-gateway\",\"message\":\"order {}\"}}\n",
+                    i
+                )
+                .into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_algorithm_round_trips() {
+        let input = batch();
+        for algorithm in [
+            CompressionAlgorithm::Lz4,
+            CompressionAlgorithm::Zstd,
+            CompressionAlgorithm::Snappy,
+        ] {
+            let compressor = Compressor::new(CompressionConfig::new(algorithm)).unwrap();
+            let mut out = Vec::new();
+            assert!(compressor.compress(&input, &mut out).unwrap());
+            assert!(out.len() < input.len(), "{:?} did not shrink", algorithm);
+            assert_ne!(out.first(), Some(&b'{'));
+            assert_eq!(&*decompress(&out).unwrap(), &input[..], "{:?}", algorithm);
+        }
+        assert!(Compressor::new(CompressionConfig::new(CompressionAlgorithm::None)).is_none());
+    }
+
+    #[test]
+    fn batch_under_the_threshold_is_left_alone() {
+        let input = batch();
+        let compressor = Compressor::new(CompressionConfig {
+            min_size: input.len() + 1,
+            ..CompressionConfig::new(CompressionAlgorithm::Zstd)
+        })
+        .unwrap();
+        let mut out = b"previous".to_vec();
+        assert!(!compressor.compress(&input, &mut out).unwrap());
+        assert_eq!(out, b"previous");
+    }
+
+    #[test]
+    fn uncompressed_ndjson_is_returned_as_is() {
+        let input = batch();
+        let output = decompress(&input).unwrap();
+        assert!(matches!(output, Cow::Borrowed(_)));
+        assert_eq!(&*output, &input[..]);
+        assert!(decompress(&[]).unwrap().is_empty());
+    }
+}
@@ -1,12 +1,19 @@
 //! Simple configuration for ultra-logger
 
+use crate::error::{LoggerError, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Main logger configuration
+///
+/// Omitted fields take their defaults, so a file only needs what it changes.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LoggerConfig {
-    /// Log level filter (debug, info, warn, error)
+    /// Log level filter (debug, info, warn, error), optionally with
+    /// per-service entries such as `info,risk=warn`; see [`crate::LevelSpec`]
     pub level: String,
     
     /// Transport configuration
@@ -22,8 +29,19 @@ impl Default for LoggerConfig {
     }
 }
 
+impl LoggerConfig {
+    /// Read a YAML (or JSON) configuration file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        serde_yaml::from_str(&text)
+            .map_err(|e| LoggerError::Config(format!("{}: {}", path.display(), e)))
+    }
+}
+
 /// Transport configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TransportConfig {
     /// Transport type: "stdout", "file", "elasticsearch"
     pub transport_type: String,
@@ -43,6 +61,7 @@ impl Default for TransportConfig {
 
 /// Connection configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ConnectionConfig {
     /// Host/endpoint
     pub host: String,
@@ -71,3 +90,316 @@ impl Default for ConnectionConfig {
         }
     }
 }
+
+/// Queue sizing and overflow behaviour
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferConfig {
+    /// Maximum entries waiting for the background processor
+    pub capacity: usize,
+
+    /// What to do when the queue is full
+    pub backpressure: BackpressurePolicy,
+
+    /// Order in which queued entries are written
+    #[serde(default)]
+    pub drain_order: DrainOrder,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 65_536,
+            backpressure: BackpressurePolicy::default(),
+            drain_order: DrainOrder::default(),
+        }
+    }
+}
+
+/// Behaviour when the logger queue is full
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackpressurePolicy {
+    /// Log calls wait until the background processor frees space
+    #[default]
+    Block,
+
+    /// Discard the entry being logged
+    DropNewest,
+
+    /// Evict the oldest queued entry to make room
+    DropOldest,
+
+    /// Append the entry to a file and replay it once the queue drains
+    /// (replayed entries are delivered after newer queued ones). Entries left
+    /// in the file by a previous run are replayed too; see
+    /// [`RecoveryReport`](crate::RecoveryReport).
+    SpillToDisk { path: PathBuf },
+}
+
+/// Order in which the background processor writes queued entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DrainOrder {
+    /// Oldest first, always
+    #[default]
+    Fifo,
+
+    /// Oldest first until at least `threshold` entries are queued; then
+    /// everything queued is written newest first, so a backlog delivers fresh
+    /// entries before stale ones. Not for audit streams that rely on order.
+    LifoOverThreshold { threshold: usize },
+}
+
+/// Which time a sink orders, partitions or indexes entries by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimestampSource {
+    /// When the entry was logged (`timestamp`)
+    #[default]
+    Logged,
+
+    /// When the event happened (`event_time`), falling back to `timestamp`
+    /// for entries logged without one
+    Event,
+}
+
+/// Per-level sampling: keep one entry in every `n` (1 keeps everything)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SamplingConfig {
+    /// Keep one Debug entry in every `debug`
+    pub debug: u32,
+
+    /// Keep one Info entry in every `info`
+    pub info: u32,
+
+    /// Keep one Warn entry in every `warn`
+    pub warn: u32,
+
+    /// Keep one Error entry in every `error`
+    pub error: u32,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            debug: 1,
+            info: 1,
+            warn: 1,
+            error: 1,
+        }
+    }
+}
+
+/// Per-level time-to-live for queued entries
+///
+/// An entry still waiting to be written once it is older than its level's TTL
+/// is dropped and counted instead, so a backlog sheds stale low-value entries
+/// rather than delaying fresh ones. `None` never expires.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtlConfig {
+    /// Age at which a queued Debug entry is dropped; `None` never expires
+    pub debug: Option<Duration>,
+
+    /// Age at which a queued Info entry is dropped; `None` never expires
+    pub info: Option<Duration>,
+
+    /// Age at which a queued Warn entry is dropped; `None` never expires
+    pub warn: Option<Duration>,
+
+    /// Age at which a queued Error entry is dropped; `None` never expires
+    pub error: Option<Duration>,
+}
+
+/// Cap on the bytes per second handed to the transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthLimit {
+    /// Sustained rate
+    pub bytes_per_second: u64,
+
+    /// Bytes that may be sent at once after an idle period
+    pub burst_bytes: u64,
+}
+
+impl BandwidthLimit {
+    /// Limit with a burst of one second's worth of data
+    pub fn per_second(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second,
+            burst_bytes: bytes_per_second,
+        }
+    }
+}
+
+/// Wall-clock jump detection (see [`clock`](crate::clock))
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ClockGuardConfig {
+    /// Change in the wall clock's offset from the monotonic clock that
+    /// counts as a jump; smaller changes are followed silently
+    pub tolerance: Duration,
+
+    /// Work jumps off gradually so timestamps never go backwards, instead
+    /// of only annotating them
+    pub compensate: bool,
+
+    /// Seconds of correction worked off per second while compensating
+    /// (at most 0.5)
+    pub slew_rate: f64,
+}
+
+impl Default for ClockGuardConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: Duration::from_millis(100),
+            compensate: true,
+            slew_rate: 0.1,
+        }
+    }
+}
+
+/// Encoding of entries in a serialized batch
+///
+/// Structured transports (OTLP, Loki, Elasticsearch, archive) accept
+/// either; [`to_ndjson`](crate::to_ndjson) converts a batch back to JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SerializationFormat {
+    /// Newline-delimited JSON
+    #[default]
+    Json,
+
+    /// MessagePack maps with field names, back to back; smaller and cheaper
+    /// to encode, for hops between our own processes
+    MessagePack,
+
+    /// Protobuf `LogBatch` (see [`proto`](crate::proto)), for consumers in
+    /// other languages
+    Protobuf,
+}
+
+/// Batch compression algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressionAlgorithm {
+    /// Hand batches over as serialized
+    #[default]
+    None,
+
+    /// LZ4 frames: cheapest to compress, for latency-sensitive hops
+    Lz4,
+
+    /// Zstandard frames at `CompressionConfig::level`: best ratio
+    Zstd,
+
+    /// Snappy framed stream
+    Snappy,
+}
+
+/// Compression applied to each serialized batch before the transport
+///
+/// Meant for transports that ship bytes as they are (file, Unix socket,
+/// shared memory, custom); read batches back with
+/// [`decompress`](crate::decompress). The structured transports (OTLP,
+/// Loki, Elasticsearch, archive) decompress on their side first, so
+/// compressing in front of them only costs CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    pub algorithm: CompressionAlgorithm,
+
+    /// Batches smaller than this many bytes are sent uncompressed
+    pub min_size: usize,
+
+    /// Zstandard level (1 to 22; 0 means the library default)
+    pub level: i32,
+}
+
+impl CompressionConfig {
+    /// Default threshold and level for `algorithm`
+    pub fn new(algorithm: CompressionAlgorithm) -> Self {
+        Self {
+            algorithm,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: CompressionAlgorithm::None,
+            min_size: 1024,
+            level: 3,
+        }
+    }
+}
+
+/// Redelivery of batches the transport failed to accept
+///
+/// Failed batches are parked and resent, oldest sequence first, whenever one
+/// is due; [`flush`](crate::UltraLogger::flush) does not wait for ones still
+/// backing off. On shutdown each parked batch gets one last attempt before
+/// going to the dead-letter file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Resends after the first attempt before a batch is dead-lettered
+    pub max_retries: u32,
+
+    /// Wait before the first resend, doubled for each one after (with
+    /// jitter) up to `max_backoff`
+    pub initial_backoff: Duration,
+
+    /// Longest wait between resends
+    pub max_backoff: Duration,
+
+    /// Failed batches held at once; when full the oldest is dead-lettered
+    pub queue_capacity: usize,
+
+    /// File that receives batches which exhaust their retries; without one
+    /// they are dropped and counted in `messages_dropped`
+    pub dead_letter_path: Option<PathBuf>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            queue_capacity: 64,
+            dead_letter_path: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_file_fills_omitted_fields_with_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(
+            &path,
+            "level: \"warn\"\ntransport:\n  transport_type: \"file\"\n  connection:\n    host: \"/var/log\"\n",
+        )
+        .unwrap();
+
+        let config = LoggerConfig::from_file(&path).unwrap();
+        assert_eq!(config.level, "warn");
+        assert_eq!(config.transport.transport_type, "file");
+        assert_eq!(config.transport.connection.host, "/var/log");
+        assert_eq!(config.transport.connection.port, 9200);
+        assert!(config.transport.connection.options.is_empty());
+    }
+
+    #[test]
+    fn from_file_reports_the_path_of_a_bad_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, "level: [info\n").unwrap();
+
+        match LoggerConfig::from_file(&path) {
+            Err(LoggerError::Config(msg)) => assert!(msg.contains("config.yaml"), "{msg}"),
+            other => panic!("expected a config error, got {other:?}"),
+        }
+        assert!(matches!(
+            LoggerConfig::from_file(dir.path().join("missing.yaml")),
+            Err(LoggerError::Io(_))
+        ));
+    }
+}
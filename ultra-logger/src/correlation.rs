@@ -0,0 +1,126 @@
+//! Correlation IDs for business workflows
+//!
+//! A correlation ID follows one workflow (an order from entry to settlement,
+//! say) across every trace, service and retry it touches, so it is kept
+//! separate from trace IDs. IDs are ULIDs: 128 bits, lexicographically
+//! sortable by creation time and rendered as 26 Crockford base32 characters.
+
+use crate::error::{LoggerError, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use ulid::{Generator, Ulid};
+
+/// Field name correlation IDs are logged under
+pub const CORRELATION_ID_FIELD: &str = "correlation_id";
+
+/// Shared generator so IDs minted in the same millisecond still sort in
+/// creation order
+static GENERATOR: Mutex<Generator> = Mutex::new(Generator::new());
+
+/// Identifier shared by every entry belonging to one business workflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CorrelationId(Ulid);
+
+impl CorrelationId {
+    /// Mint a new ID, monotonic within this process
+    pub fn new() -> Self {
+        let mut generator = GENERATOR.lock().unwrap_or_else(|e| e.into_inner());
+        // Only fails once 2^80 IDs have been minted in a single millisecond
+        match generator.generate() {
+            Ok(id) => CorrelationId(id),
+            Err(_) => CorrelationId(Ulid::new()),
+        }
+    }
+
+    /// Milliseconds since the Unix epoch at which the ID was minted
+    pub fn timestamp_ms(&self) -> u64 {
+        self.0.timestamp_ms()
+    }
+}
+
+impl Default for CorrelationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for CorrelationId {
+    type Err = LoggerError;
+
+    /// Parse an ID received from another service
+    fn from_str(s: &str) -> Result<Self> {
+        Ulid::from_string(s)
+            .map(CorrelationId)
+            .map_err(|e| LoggerError::InvalidCorrelationId(format!("{}: {}", s, e)))
+    }
+}
+
+impl Serialize for CorrelationId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CorrelationId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_parses_back_to_the_same_id() {
+        let id = CorrelationId::new();
+        let text = id.to_string();
+        assert_eq!(text.len(), 26);
+        assert_eq!(text.parse::<CorrelationId>().unwrap(), id);
+        // Crockford base32 is case-insensitive
+        assert_eq!(text.to_lowercase().parse::<CorrelationId>().unwrap(), id);
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{}\"", text));
+        assert_eq!(serde_json::from_str::<CorrelationId>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn ids_sort_in_creation_order() {
+        let ids: Vec<CorrelationId> = (0..100).map(|_| CorrelationId::new()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids
+            .windows(2)
+            .all(|pair| pair[0].to_string() < pair[1].to_string()));
+    }
+
+    #[test]
+    fn invalid_ids_are_rejected() {
+        for text in [
+            "",
+            "01ARZ3NDEKTSV4RRFFQ69G5FA",
+            "01ARZ3NDEKTSV4RRFFQ69G5FAVV",
+            // U is not in the Crockford alphabet
+            "01ARZ3NDEKTSV4RRFFQ69G5FAU",
+            "01ARZ3NDEKTSV4RRFFQ69G5FA-",
+        ] {
+            match text.parse::<CorrelationId>() {
+                Err(LoggerError::InvalidCorrelationId(reason)) => {
+                    assert!(reason.starts_with(text), "{}", reason)
+                }
+                other => panic!("{:?}: {:?}", text, other),
+            }
+        }
+        assert!(serde_json::from_str::<CorrelationId>("\"not-an-id\"").is_err());
+        assert!(serde_json::from_str::<CorrelationId>("42").is_err());
+    }
+}
@@ -0,0 +1,459 @@
+//! Elasticsearch / OpenSearch output through the `_bulk` API
+//!
+//! Each batch is re-read from NDJSON and sent as `create` actions, with the
+//! target index named from the entry's own time (so a batch spanning
+//! midnight lands in both daily indices). A whole request refused with 429
+//! is retried by the shared HTTP client; documents refused individually with
+//! 429 are resent with the same backoff. Any other per-document rejection,
+//! and 429s still refused after the last retry, go to the dead-letter file.
+//!
+//! Every document gets an `_id` derived from its content (and its position
+//! among identical entries in the batch), so when a batch split over several
+//! requests fails partway and is resent, the documents that already went in
+//! come back as 409 conflicts and are counted as indexed, not duplicated.
+
+use crate::error::{LoggerError, Result};
+use crate::http::{Endpoint, RetryPolicy};
+use crate::transport::Transport;
+use crate::{LogEntry, TimestampSource};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::format::{Item, StrftimeItems};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Elasticsearch bulk indexing configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElasticsearchConfig {
+    /// Cluster URL, optionally with a path prefix (e.g. behind a proxy);
+    /// plain `http://` only
+    pub endpoint: String,
+
+    /// Index name as a strftime pattern over each entry's time
+    pub index_pattern: String,
+
+    /// Time the index pattern is applied to
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+
+    /// Basic auth credentials, used when both are set. The endpoint is
+    /// plain `http://`, so they cross the network in clear text and are
+    /// refused unless `allow_insecure_auth` is set
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Send credentials over `http://` anyway, e.g. on a loopback or
+    /// otherwise trusted network
+    #[serde(default)]
+    pub allow_insecure_auth: bool,
+
+    /// Documents per `_bulk` request; larger batches are split
+    pub bulk_size: usize,
+
+    /// NDJSON file for rejected documents; without one a rejection fails
+    /// the batch
+    pub dead_letter_path: Option<PathBuf>,
+
+    /// Attempts after the first for retryable failures
+    pub max_retries: u32,
+
+    /// Wait before the first retry, doubled for each one after, up to 5s
+    pub retry_backoff: Duration,
+
+    /// Limit for connecting, sending and reading the response, per attempt
+    pub timeout: Duration,
+}
+
+impl Default for ElasticsearchConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:9200".to_string(),
+            index_pattern: "trading-logs-%Y.%m.%d".to_string(),
+            timestamp_source: TimestampSource::default(),
+            username: None,
+            password: None,
+            allow_insecure_auth: false,
+            bulk_size: 1000,
+            dead_letter_path: None,
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(100),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Indexes batches into Elasticsearch or OpenSearch
+pub struct ElasticsearchTransport {
+    endpoint: Endpoint,
+    bulk_path: String,
+    retry: RetryPolicy,
+    index_pattern: String,
+    timestamp_source: TimestampSource,
+    authorization: Option<String>,
+    bulk_size: usize,
+    dead_letter_path: Option<PathBuf>,
+}
+
+/// One document on its way to the cluster
+struct Document {
+    index: String,
+    id: String,
+    source: String,
+}
+
+/// `_id` for `source`, the `occurrence`th identical entry in its batch
+fn document_id(source: &str, occurrence: u32) -> String {
+    let half = |salt: u8| {
+        let mut hasher = DefaultHasher::new();
+        (salt, source, occurrence).hash(&mut hasher);
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", half(0), half(1))
+}
+
+impl ElasticsearchTransport {
+    pub fn new(config: ElasticsearchConfig) -> Result<Self> {
+        if StrftimeItems::new(&config.index_pattern).any(|item| matches!(item, Item::Error)) {
+            return Err(LoggerError::Transport(format!(
+                "invalid index pattern: {}",
+                config.index_pattern
+            )));
+        }
+        let authorization = match (&config.username, &config.password) {
+            (Some(_), Some(_)) if !config.allow_insecure_auth => {
+                return Err(LoggerError::Transport(format!(
+                    "refusing to send credentials in clear text to {}; \
+                     set allow_insecure_auth to send them anyway",
+                    config.endpoint
+                )));
+            }
+            (Some(username), Some(password)) => Some(format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password))
+            )),
+            _ => None,
+        };
+
+        let endpoint = Endpoint::parse(&config.endpoint, "/")?;
+        Ok(Self {
+            bulk_path: format!("{}/_bulk", endpoint.path().trim_end_matches('/')),
+            endpoint,
+            retry: RetryPolicy {
+                max_retries: config.max_retries,
+                backoff: config.retry_backoff,
+                timeout: config.timeout,
+            },
+            index_pattern: config.index_pattern,
+            timestamp_source: config.timestamp_source,
+            authorization,
+            bulk_size: config.bulk_size.max(1),
+            dead_letter_path: config.dead_letter_path,
+        })
+    }
+
+    /// Send `documents` until each is indexed or rejected for good, and
+    /// return the rejected ones with their status and error
+    async fn index(&self, mut documents: Vec<Document>) -> Result<Vec<(Document, Value)>> {
+        let mut rejected = Vec::new();
+        let mut backoff = self.retry.backoff;
+        let mut attempt = 0;
+        loop {
+            let mut throttled = Vec::new();
+            for (document, item) in self.bulk(documents).await? {
+                match item["status"].as_u64() {
+                    // 409: indexed by an earlier send of the same batch
+                    Some(200..=299 | 409) => {}
+                    Some(429) => throttled.push((document, item)),
+                    _ => rejected.push((document, item)),
+                }
+            }
+            if throttled.is_empty() {
+                return Ok(rejected);
+            }
+            if attempt == self.retry.max_retries {
+                rejected.extend(throttled);
+                return Ok(rejected);
+            }
+
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff = self.retry.next_backoff(backoff);
+            documents = throttled
+                .into_iter()
+                .map(|(document, _)| document)
+                .collect();
+        }
+    }
+
+    /// One `_bulk` request; pairs each document with its response item
+    async fn bulk(&self, documents: Vec<Document>) -> Result<Vec<(Document, Value)>> {
+        let mut body = Vec::new();
+        for document in &documents {
+            serde_json::to_writer(
+                &mut body,
+                &json!({ "create": { "_index": document.index, "_id": document.id } }),
+            )?;
+            body.push(b'\n');
+            body.extend_from_slice(document.source.as_bytes());
+            body.push(b'\n');
+        }
+
+        let mut headers = vec![("Content-Type", "application/x-ndjson")];
+        if let Some(authorization) = &self.authorization {
+            headers.push(("Authorization", authorization));
+        }
+        let response = self
+            .endpoint
+            .post_with_retry(&self.bulk_path, &headers, &body, &self.retry)
+            .await
+            .map_err(|reason| {
+                LoggerError::Transport(format!(
+                    "bulk request to {} failed: {}",
+                    self.endpoint.url(),
+                    reason
+                ))
+            })?;
+
+        let mut response: Value = serde_json::from_slice(&response.body)?;
+        let items = match response["items"].take() {
+            Value::Array(items) if items.len() == documents.len() => items,
+            _ => {
+                return Err(LoggerError::Transport(
+                    "bulk response does not match request".to_string(),
+                ))
+            }
+        };
+        Ok(documents
+            .into_iter()
+            .zip(items)
+            .map(|(document, mut item)| (document, item["create"].take()))
+            .collect())
+    }
+
+    fn dead_letter(&self, rejected: &[(Document, Value)]) -> Result<()> {
+        let Some(path) = &self.dead_letter_path else {
+            return Err(LoggerError::Transport(format!(
+                "{} documents rejected, first: {}",
+                rejected.len(),
+                rejected[0].1["error"]
+            )));
+        };
+
+        let mut out = Vec::new();
+        for (document, item) in rejected {
+            let source: Value = serde_json::from_str(&document.source)?;
+            serde_json::to_writer(
+                &mut out,
+                &json!({
+                    "index": document.index,
+                    "status": item["status"],
+                    "error": item["error"],
+                    "document": source,
+                }),
+            )?;
+            out.push(b'\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&out)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for ElasticsearchTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        let batch = &*crate::to_ndjson(batch)?;
+        let mut documents = Vec::new();
+        let mut seen: HashMap<String, u32> = HashMap::new();
+        for entry in serde_json::Deserializer::from_slice(batch).into_iter::<LogEntry>() {
+            let entry = entry?;
+            let source = serde_json::to_string(&entry)?;
+            let occurrence = seen.entry(source.clone()).or_default();
+            documents.push(Document {
+                index: entry
+                    .time(self.timestamp_source)
+                    .format(&self.index_pattern)
+                    .to_string(),
+                id: document_id(&source, *occurrence),
+                source,
+            });
+            *occurrence += 1;
+        }
+
+        let mut rejected = Vec::new();
+        while !documents.is_empty() {
+            let rest = documents.split_off(documents.len().min(self.bulk_size));
+            rejected.extend(self.index(documents).await?);
+            documents = rest;
+        }
+
+        if rejected.is_empty() {
+            Ok(())
+        } else {
+            self.dead_letter(&rejected)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Request line path and body of each request a fake cluster received
+    type Requests = std::sync::Arc<std::sync::Mutex<Vec<(String, String)>>>;
+
+    /// Serve one canned `(status, body)` per request, in order
+    async fn fake_cluster(responses: Vec<(u16, String)>) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Requests::default();
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut raw = Vec::new();
+                let mut buf = [0; 4096];
+                let (head, length) = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    raw.extend_from_slice(&buf[..n]);
+                    if let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+                        let head = String::from_utf8(raw[..end].to_vec()).unwrap();
+                        let length: usize = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("Content-Length: "))
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                        raw.drain(..end + 4);
+                        break (head, length);
+                    }
+                };
+                while raw.len() < length {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    raw.extend_from_slice(&buf[..n]);
+                }
+                let path = head.split_whitespace().nth(1).unwrap().to_string();
+                received
+                    .lock()
+                    .unwrap()
+                    .push((path, String::from_utf8(raw).unwrap()));
+
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}", address), requests)
+    }
+
+    fn items(statuses: &[u16]) -> (u16, String) {
+        let items: Vec<Value> = statuses
+            .iter()
+            .map(|status| json!({ "create": { "status": status } }))
+            .collect();
+        (200, json!({ "items": items }).to_string())
+    }
+
+    fn ids(body: &str) -> Vec<String> {
+        body.lines()
+            .step_by(2)
+            .map(|action| {
+                let action: Value = serde_json::from_str(action).unwrap();
+                action["create"]["_id"].as_str().unwrap().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn document_ids_are_stable_and_distinguish_duplicates() {
+        assert_eq!(document_id("{}", 0), document_id("{}", 0));
+        assert_ne!(document_id("{}", 0), document_id("{}", 1));
+        assert_ne!(document_id("{}", 0), document_id("{ }", 0));
+        assert_eq!(document_id("{}", 0).len(), 32);
+    }
+
+    #[test]
+    fn credentials_over_plain_http_need_an_opt_in() {
+        let config = ElasticsearchConfig {
+            username: Some("elastic".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        match ElasticsearchTransport::new(config.clone()) {
+            Err(LoggerError::Transport(reason)) => {
+                assert!(reason.contains("allow_insecure_auth"), "{}", reason)
+            }
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+
+        let transport = ElasticsearchTransport::new(ElasticsearchConfig {
+            allow_insecure_auth: true,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(
+            transport.authorization.as_deref(),
+            Some("Basic ZWxhc3RpYzpzZWNyZXQ=")
+        );
+
+        // Without both credentials nothing is sent, so nothing to refuse
+        let anonymous = ElasticsearchTransport::new(ElasticsearchConfig {
+            username: Some("elastic".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(anonymous.authorization.is_none());
+    }
+
+    #[tokio::test]
+    async fn resent_batch_reuses_ids_and_counts_conflicts_as_indexed() {
+        // First send: chunk 1 indexed, chunk 2 refused. Resend: chunk 1
+        // conflicts with what went in, chunk 2 is indexed.
+        let (url, requests) = fake_cluster(vec![
+            items(&[201]),
+            (400, "bad request".to_string()),
+            items(&[409]),
+            items(&[201]),
+        ])
+        .await;
+        let mut transport = ElasticsearchTransport::new(ElasticsearchConfig {
+            endpoint: format!("{}/search/", url),
+            bulk_size: 1,
+            max_retries: 0,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let entry =
+            r#"{"timestamp":"2026-01-02T03:04:05Z","level":"INFO","service":"s","message":"m"}"#;
+        let batch = format!("{}\n{}\n", entry, entry);
+        assert!(transport.send_batch(batch.as_bytes()).await.is_err());
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        transport.send_batch(batch.as_bytes()).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter().all(|(path, _)| path == "/search/_bulk"));
+        let sent: Vec<Vec<String>> = requests.iter().map(|(_, body)| ids(body)).collect();
+        // Identical entries get distinct ids, the same ones on each send
+        assert_ne!(sent[0], sent[1]);
+        assert_eq!(sent[0], sent[2]);
+        assert_eq!(sent[1], sent[3]);
+    }
+}
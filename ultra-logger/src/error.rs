@@ -0,0 +1,50 @@
+//! Error types for ultra-logger
+
+use thiserror::Error;
+
+/// Errors returned by the logger
+#[derive(Debug, Error)]
+pub enum LoggerError {
+    /// The background processor has stopped and no longer accepts entries
+    #[error("logger has been shut down")]
+    ChannelClosed,
+
+    /// A transport could not be opened or written to
+    #[error("transport error: {0}")]
+    Transport(String),
+
+    /// Underlying I/O failure
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Entry could not be serialized
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    /// A batch could not be encoded or decoded in its wire format
+    #[error("invalid batch: {0}")]
+    InvalidBatch(String),
+
+    /// Event ID or error code is malformed or not in the registry
+    #[error("invalid event code: {0}")]
+    InvalidEventCode(String),
+
+    /// Correlation ID could not be parsed
+    #[error("invalid correlation id: {0}")]
+    InvalidCorrelationId(String),
+
+    /// Configuration file could not be read as a `LoggerConfig`
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    /// Level name or level directive could not be parsed
+    #[error("invalid log level: {0}")]
+    InvalidLevel(String),
+
+    /// A process-wide logger has already been installed
+    #[error("a global logger is already installed")]
+    AlreadyInitialized,
+}
+
+/// Result alias used throughout ultra-logger
+pub type Result<T> = std::result::Result<T, LoggerError>;
@@ -0,0 +1,154 @@
+//! Stable event IDs and error codes
+//!
+//! Entries carrying a code can be muted here, or sent to a partition of their
+//! own with [`PartitionedLoggerBuilder::with_route`](crate::PartitionedLoggerBuilder::with_route).
+
+use crate::error::{LoggerError, Result};
+use std::collections::HashSet;
+
+/// Longest accepted event ID or error code
+const MAX_CODE_LEN: usize = 64;
+
+/// Check that a code is non-empty, short and made of `[A-Za-z0-9_.-]`
+pub fn validate_code(code: &str) -> Result<()> {
+    let well_formed = !code.is_empty()
+        && code.len() <= MAX_CODE_LEN
+        && code
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-'));
+
+    if well_formed {
+        Ok(())
+    } else {
+        Err(LoggerError::InvalidEventCode(code.to_string()))
+    }
+}
+
+/// Known event IDs and error codes
+///
+/// Once a registry is attached to a logger, only registered codes are
+/// accepted, so a typo can't silently break an alert keyed on the code.
+#[derive(Debug, Clone, Default)]
+pub struct EventRegistry {
+    events: HashSet<String>,
+    error_codes: HashSet<String>,
+    muted: HashSet<String>,
+}
+
+impl EventRegistry {
+    /// Empty registry, which accepts no codes until some are registered
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an event ID
+    pub fn with_event(mut self, event_id: impl Into<String>) -> Result<Self> {
+        let event_id = event_id.into();
+        validate_code(&event_id)?;
+        self.events.insert(event_id);
+        Ok(self)
+    }
+
+    /// Register an error code
+    pub fn with_error_code(mut self, error_code: impl Into<String>) -> Result<Self> {
+        let error_code = error_code.into();
+        validate_code(&error_code)?;
+        self.error_codes.insert(error_code);
+        Ok(self)
+    }
+
+    /// Discard entries carrying this event ID or error code
+    pub fn mute(mut self, code: impl Into<String>) -> Result<Self> {
+        let code = code.into();
+        validate_code(&code)?;
+        self.muted.insert(code);
+        Ok(self)
+    }
+
+    /// Validate the codes on an entry; `Ok(false)` means it is muted
+    pub(crate) fn check(&self, event_id: Option<&str>, error_code: Option<&str>) -> Result<bool> {
+        if let Some(event_id) = event_id {
+            if !self.events.contains(event_id) {
+                return Err(LoggerError::InvalidEventCode(event_id.to_string()));
+            }
+        }
+        if let Some(error_code) = error_code {
+            if !self.error_codes.contains(error_code) {
+                return Err(LoggerError::InvalidEventCode(error_code.to_string()));
+            }
+        }
+
+        let muted = [event_id, error_code]
+            .into_iter()
+            .flatten()
+            .any(|code| self.muted.contains(code));
+        Ok(!muted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_must_be_short_and_plain() {
+        for code in [
+            "ORD-FILL",
+            "risk.limit_breached",
+            "E1042",
+            &"A".repeat(MAX_CODE_LEN),
+        ] {
+            assert!(validate_code(code).is_ok(), "{code}");
+        }
+        for code in [
+            "",
+            "order filled",
+            "E1042!",
+            "ORDER/FILL",
+            "é",
+            &"A".repeat(MAX_CODE_LEN + 1),
+        ] {
+            assert!(
+                matches!(validate_code(code), Err(LoggerError::InvalidEventCode(_))),
+                "{code}"
+            );
+        }
+    }
+
+    #[test]
+    fn registry_accepts_only_registered_codes() {
+        let registry = EventRegistry::new()
+            .with_event("ORD-FILL")
+            .unwrap()
+            .with_error_code("E1042")
+            .unwrap();
+        assert!(registry.check(None, None).unwrap());
+        assert!(registry.check(Some("ORD-FILL"), None).unwrap());
+        assert!(registry.check(Some("ORD-FILL"), Some("E1042")).unwrap());
+
+        // Registered as the other kind doesn't count
+        assert!(registry.check(Some("E1042"), None).is_err());
+        assert!(registry.check(None, Some("ORD-FILL")).is_err());
+        assert!(registry.check(Some("ORD-FIL"), None).is_err());
+    }
+
+    #[test]
+    fn muted_codes_are_discarded() {
+        let registry = EventRegistry::new()
+            .with_event("HEARTBEAT")
+            .unwrap()
+            .with_event("ORD-FILL")
+            .unwrap()
+            .mute("HEARTBEAT")
+            .unwrap();
+        assert!(!registry.check(Some("HEARTBEAT"), None).unwrap());
+        assert!(registry.check(Some("ORD-FILL"), None).unwrap());
+    }
+
+    #[test]
+    fn registrations_reject_malformed_codes() {
+        assert!(EventRegistry::new().with_event("order filled").is_err());
+        assert!(EventRegistry::new().with_error_code("").is_err());
+        assert!(EventRegistry::new().mute("HEART BEAT").is_err());
+    }
+}
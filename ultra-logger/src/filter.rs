@@ -0,0 +1,257 @@
+//! Minimum-level filtering with per-service levels and overrides
+
+use crate::error::{LoggerError, Result};
+use crate::LogLevel;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Environment variable read by [`LevelSpec::from_env`]
+pub const LEVEL_ENV_VAR: &str = "ULTRA_LOGGER_LEVEL";
+
+/// Level directives such as `info,market_data=debug,risk=warn`
+///
+/// A bare level sets the default minimum; `service=level` pairs set the
+/// minimum for one service. Whitespace around entries is ignored and later
+/// entries win.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelSpec {
+    /// Minimum level for services without an entry of their own
+    pub default: Option<LogLevel>,
+
+    /// Per-service minimum levels
+    pub services: BTreeMap<String, LogLevel>,
+}
+
+impl LevelSpec {
+    /// Parse directives like `info,market_data=debug`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut parsed = LevelSpec::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((service, level)) => {
+                    let service = service.trim();
+                    if service.is_empty() {
+                        return Err(LoggerError::InvalidLevel(directive.to_string()));
+                    }
+                    parsed
+                        .services
+                        .insert(service.to_string(), level.trim().parse()?);
+                }
+                None => parsed.default = Some(directive.parse()?),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Parse [`LEVEL_ENV_VAR`] if it is set
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(LEVEL_ENV_VAR) {
+            Ok(spec) => Self::parse(&spec).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl FromStr for LevelSpec {
+    type Err = LoggerError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+/// Level applied to one service until it expires
+struct LevelOverride {
+    level: LogLevel,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct ServiceLevels {
+    /// Persistent per-service minimums
+    fixed: HashMap<String, LogLevel>,
+    /// Time-boxed overrides; these win over `fixed` until they expire
+    temporary: HashMap<String, LevelOverride>,
+    /// Earliest expiry in `temporary`, when the overrides need pruning
+    next_expiry: Option<Instant>,
+}
+
+impl ServiceLevels {
+    fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && self.temporary.is_empty()
+    }
+}
+
+/// Decides whether an entry at a given level is kept for a service
+pub(crate) struct LevelFilter {
+    /// Minimum level for services without their own, as `LogLevel as u8`
+    base: AtomicU8,
+    services: RwLock<ServiceLevels>,
+    /// Fast path: skip the maps entirely while no service has its own level
+    has_service_levels: AtomicBool,
+}
+
+impl LevelFilter {
+    pub(crate) fn new(base: LogLevel, fixed: BTreeMap<String, LogLevel>) -> Self {
+        let services = ServiceLevels {
+            fixed: fixed.into_iter().collect(),
+            temporary: HashMap::new(),
+            next_expiry: None,
+        };
+        Self {
+            base: AtomicU8::new(base as u8),
+            has_service_levels: AtomicBool::new(!services.is_empty()),
+            services: RwLock::new(services),
+        }
+    }
+
+    pub(crate) fn enabled(&self, service: &str, level: LogLevel) -> bool {
+        level >= self.min_level(service)
+    }
+
+    fn min_level(&self, service: &str) -> LogLevel {
+        if !self.has_service_levels.load(Ordering::Acquire) {
+            return self.base();
+        }
+
+        let services = self.services.read().unwrap_or_else(|e| e.into_inner());
+        if services
+            .next_expiry
+            .is_some_and(|expiry| expiry <= Instant::now())
+        {
+            // Slow path, once per expiry: drop what expired so the flag can
+            // clear when nothing else is left
+            drop(services);
+            self.prune();
+            return self.min_level(service);
+        }
+        match services.temporary.get(service) {
+            Some(o) => o.level,
+            None => match services.fixed.get(service) {
+                Some(level) => *level,
+                None => self.base(),
+            },
+        }
+    }
+
+    fn prune(&self) {
+        let now = Instant::now();
+        self.update(|services| {
+            services.temporary.retain(|_, o| o.expires_at > now);
+        });
+    }
+
+    pub(crate) fn base(&self) -> LogLevel {
+        match self.base.load(Ordering::Relaxed) {
+            0 => LogLevel::Debug,
+            1 => LogLevel::Info,
+            2 => LogLevel::Warn,
+            _ => LogLevel::Error,
+        }
+    }
+
+    pub(crate) fn set_base(&self, level: LogLevel) {
+        self.base.store(level as u8, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_service_level(&self, service: &str, level: LogLevel) {
+        self.update(|services| {
+            services.fixed.insert(service.to_string(), level);
+        });
+    }
+
+    pub(crate) fn clear_service_level(&self, service: &str) {
+        self.update(|services| {
+            services.fixed.remove(service);
+        });
+    }
+
+    /// Replace the default (if given) and every persistent per-service level
+    pub(crate) fn apply_spec(&self, spec: &LevelSpec) {
+        if let Some(level) = spec.default {
+            self.set_base(level);
+        }
+        self.update(|services| {
+            services.fixed = spec
+                .services
+                .iter()
+                .map(|(service, level)| (service.clone(), *level))
+                .collect();
+        });
+    }
+
+    /// Apply `level` to `service` for `duration`, replacing any earlier override
+    pub(crate) fn set_override(&self, service: &str, level: LogLevel, duration: Duration) {
+        let now = Instant::now();
+        self.update(|services| {
+            services.temporary.retain(|_, o| o.expires_at > now);
+            services.temporary.insert(
+                service.to_string(),
+                LevelOverride {
+                    level,
+                    expires_at: now + duration,
+                },
+            );
+        });
+    }
+
+    pub(crate) fn clear_override(&self, service: &str) {
+        self.update(|services| {
+            services.temporary.remove(service);
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ServiceLevels)) {
+        let mut services = self.services.write().unwrap_or_else(|e| e.into_inner());
+        f(&mut services);
+        services.next_expiry = services.temporary.values().map(|o| o.expires_at).min();
+        self.has_service_levels
+            .store(!services.is_empty(), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> LevelFilter {
+        LevelFilter::new(LogLevel::Info, BTreeMap::new())
+    }
+
+    #[test]
+    fn expired_override_is_pruned_and_fast_path_restored() {
+        let filter = filter();
+        filter.set_override("risk", LogLevel::Debug, Duration::from_millis(20));
+        assert!(filter.enabled("risk", LogLevel::Debug));
+        assert!(filter.has_service_levels.load(Ordering::Acquire));
+
+        std::thread::sleep(Duration::from_millis(30));
+        // Found expired by a lookup for another service
+        assert!(!filter.enabled("engine", LogLevel::Debug));
+        assert!(!filter.has_service_levels.load(Ordering::Acquire));
+        assert!(!filter.enabled("risk", LogLevel::Debug));
+    }
+
+    #[test]
+    fn pruning_keeps_fixed_levels_and_live_overrides() {
+        let filter = filter();
+        filter.set_service_level("market_data", LogLevel::Debug);
+        filter.set_override("risk", LogLevel::Debug, Duration::from_millis(20));
+        filter.set_override("oms", LogLevel::Error, Duration::from_secs(3600));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!filter.enabled("risk", LogLevel::Debug));
+        assert!(filter.enabled("market_data", LogLevel::Debug));
+        assert!(!filter.enabled("oms", LogLevel::Warn));
+        assert!(filter.has_service_levels.load(Ordering::Acquire));
+
+        let services = filter.services.read().unwrap();
+        assert!(!services.temporary.contains_key("risk"));
+        assert!(services
+            .next_expiry
+            .is_some_and(|expiry| expiry > Instant::now()));
+    }
+}
@@ -0,0 +1,155 @@
+//! Wire formats for serialized batches
+//!
+//! A JSON batch is NDJSON. A MessagePack batch is the entries' MessagePack
+//! maps back to back (each value is self-delimiting), with field names kept
+//! so optional fields can be left out as in JSON. A Protobuf batch is one
+//! single-entry `LogBatch` per entry back to back, which parses as a single
+//! `LogBatch` holding them all. [`to_ndjson`] tells them apart by the first
+//! byte: `{` for JSON, the `schema_version` tag for Protobuf, and anything
+//! else is MessagePack (whose maps never start with either).
+
+use crate::compress::decompress;
+use crate::config::SerializationFormat;
+use crate::error::{LoggerError, Result};
+use crate::proto::{self, SCHEMA_VERSION};
+use crate::LogEntry;
+use prost::Message;
+use std::borrow::Cow;
+
+/// Key of field 1 (`schema_version`, varint) that starts a Protobuf batch
+const PROTOBUF_TAG: u8 = 0x08;
+
+/// Append one entry to a batch in `format`
+pub(crate) fn write_entry(
+    format: SerializationFormat,
+    out: &mut Vec<u8>,
+    entry: &LogEntry,
+) -> Result<()> {
+    match format {
+        SerializationFormat::Json => {
+            serde_json::to_writer(&mut *out, entry)?;
+            out.push(b'\n');
+        }
+        SerializationFormat::MessagePack => {
+            rmp_serde::encode::write_named(out, entry)
+                .map_err(|e| LoggerError::InvalidBatch(e.to_string()))?;
+        }
+        SerializationFormat::Protobuf => {
+            let batch = proto::LogBatch {
+                schema_version: SCHEMA_VERSION,
+                entries: vec![entry.to_proto()],
+            };
+            batch
+                .encode(out)
+                .map_err(|e| LoggerError::InvalidBatch(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Turn any batch the logger can produce back into NDJSON
+///
+/// Decompresses (see [`decompress`]) and converts MessagePack; a batch that
+/// is already NDJSON is returned as is. For the final hop to a sink that
+/// wants JSON, after intermediate hops used a compact encoding.
+pub fn to_ndjson(batch: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let batch = decompress(batch)?;
+    if batch.first().is_none_or(|&b| b == b'{') {
+        return Ok(batch);
+    }
+
+    let mut json = Vec::with_capacity(batch.len() * 2);
+    if batch[0] == PROTOBUF_TAG {
+        let decoded = proto::LogBatch::decode(&*batch)
+            .map_err(|e| LoggerError::InvalidBatch(e.to_string()))?;
+        for entry in decoded.entries {
+            serde_json::to_writer(&mut json, &LogEntry::from_proto(entry)?)?;
+            json.push(b'\n');
+        }
+        return Ok(Cow::Owned(json));
+    }
+
+    let mut remaining: &[u8] = &batch;
+    while !remaining.is_empty() {
+        let entry: LogEntry = rmp_serde::decode::from_read(&mut remaining)
+            .map_err(|e| LoggerError::InvalidBatch(e.to_string()))?;
+        serde_json::to_writer(&mut json, &entry)?;
+        json.push(b'\n');
+    }
+    Ok(Cow::Owned(json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LogLevel, LogValue};
+    use chrono::DateTime;
+    use std::collections::BTreeMap;
+
+    fn entries() -> Vec<LogEntry> {
+        let entry = |level, message: &str| LogEntry {
+            timestamp: DateTime::from_timestamp_nanos(1_773_000_000_123_456_789),
+            level,
+            service: "order-gateway".into(),
+            message: message.into(),
+            event_time: None,
+            event_id: None,
+            error_code: None,
+            fields: BTreeMap::new(),
+        };
+        let mut filled = entry(LogLevel::Info, "order filled");
+        filled.event_time = Some(DateTime::from_timestamp_nanos(1_773_000_000_100_000_001));
+        filled.event_id = Some("ORD-FILL".into());
+        filled.fields = BTreeMap::from([
+            ("filled".to_string(), LogValue::Bool(true)),
+            ("position".to_string(), LogValue::I64(-250)),
+            ("order_id".to_string(), LogValue::U64(u64::MAX)),
+            ("price".to_string(), LogValue::F64(45000.25)),
+            ("symbol".to_string(), LogValue::String("BTCUSD".into())),
+        ]);
+        let mut rejected = entry(LogLevel::Error, "order rejected");
+        rejected.error_code = Some("E1042".into());
+        vec![entry(LogLevel::Debug, "tick"), filled, rejected]
+    }
+
+    fn encode(format: SerializationFormat, entries: &[LogEntry]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for entry in entries {
+            write_entry(format, &mut out, entry).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn every_format_converts_back_to_the_same_ndjson() {
+        let entries = entries();
+        let json = encode(SerializationFormat::Json, &entries);
+        assert!(matches!(to_ndjson(&json).unwrap(), Cow::Borrowed(_)));
+
+        for format in [
+            SerializationFormat::MessagePack,
+            SerializationFormat::Protobuf,
+        ] {
+            let batch = encode(format, &entries);
+            assert_ne!(batch, json);
+            assert_eq!(
+                String::from_utf8(to_ndjson(&batch).unwrap().into_owned()).unwrap(),
+                String::from_utf8(json.clone()).unwrap(),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn empty_batch_is_empty_ndjson() {
+        for format in [
+            SerializationFormat::Json,
+            SerializationFormat::MessagePack,
+            SerializationFormat::Protobuf,
+        ] {
+            let batch = encode(format, &[]);
+            assert!(to_ndjson(&batch).unwrap().is_empty(), "{:?}", format);
+        }
+    }
+}
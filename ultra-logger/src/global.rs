@@ -0,0 +1,110 @@
+//! Process-wide logger used by the [`info!`](crate::info) family of macros
+
+use crate::error::{LoggerError, Result};
+use crate::{LogLevel, LoggerConfig, UltraLogger};
+use std::sync::OnceLock;
+
+static GLOBAL_LOGGER: OnceLock<UltraLogger> = OnceLock::new();
+
+/// Build a logger from `config` and install it as the process-wide instance
+///
+/// Fails with [`LoggerError::AlreadyInitialized`] on a second call, before
+/// building anything. Call `global().unwrap().shutdown().await` before
+/// exiting to write out what is still queued.
+///
+/// ```
+/// use ultra_logger::{LoggerConfig, Result};
+///
+/// # fn main() -> Result<()> {
+/// ultra_logger::init("risk-engine", LoggerConfig::default())?;
+/// ultra_logger::info!("limits loaded for {} accounts", 42);
+/// # Ok(())
+/// # }
+/// ```
+pub fn init(service: impl Into<String>, config: LoggerConfig) -> Result<&'static UltraLogger> {
+    if GLOBAL_LOGGER.get().is_some() {
+        return Err(LoggerError::AlreadyInitialized);
+    }
+    let logger = UltraLogger::builder()
+        .with_service(service)
+        .with_config(&config)?
+        .build()?;
+
+    GLOBAL_LOGGER
+        .set(logger)
+        .map_err(|_| LoggerError::AlreadyInitialized)?;
+    Ok(global().expect("global logger was just installed"))
+}
+
+/// The process-wide logger, if [`init`] has been called
+pub fn global() -> Option<&'static UltraLogger> {
+    GLOBAL_LOGGER.get()
+}
+
+/// Log through the process-wide logger; a no-op before [`init`]
+///
+/// The message is only built when the level is enabled. Errors are not
+/// returned; they show up in the logger's stats like any shed entry.
+///
+/// Under `BackpressurePolicy::Block` a full queue blocks the calling thread,
+/// except inside a tokio runtime, where the entry is dropped and counted in
+/// `dropped_newest` instead: a blocked runtime thread may be the one the
+/// processor needs to make room.
+pub fn log_global(level: LogLevel, message: impl FnOnce() -> String) {
+    let Some(logger) = global() else {
+        return;
+    };
+    if logger.enabled(level) {
+        let _ = logger.log_nonblocking(level, message());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_on;
+    use std::path::Path;
+
+    /// The process-wide logger, writing to a file; installed once for every
+    /// test in the binary
+    fn installed() -> (&'static UltraLogger, &'static Path) {
+        static DIR: OnceLock<tempfile::TempDir> = OnceLock::new();
+        let dir = DIR.get_or_init(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let mut config = LoggerConfig::default();
+            config.transport.transport_type = "file".into();
+            config.transport.connection.host = dir.path().display().to_string();
+            init("global-test", config).unwrap();
+            dir
+        });
+        (global().unwrap(), dir.path())
+    }
+
+    #[test]
+    fn second_init_is_refused_before_building() {
+        installed();
+        // Would fail to build; the earlier check answers first
+        let mut config = LoggerConfig::default();
+        config.transport.transport_type = "no-such-transport".into();
+        assert!(matches!(
+            init("again", config),
+            Err(LoggerError::AlreadyInitialized)
+        ));
+    }
+
+    #[test]
+    fn macro_reaches_the_transport() {
+        let (logger, dir) = installed();
+        crate::warn!("limit breached on {} accounts", 3);
+        block_on(logger.flush()).unwrap();
+
+        let written = std::fs::read_to_string(dir.join("ultra-logger.log")).unwrap();
+        let entry = written
+            .lines()
+            .map(|line| serde_json::from_str::<crate::LogEntry>(line).unwrap())
+            .find(|entry| entry.message == "limit breached on 3 accounts")
+            .expect("macro entry written");
+        assert_eq!(entry.level, LogLevel::Warn);
+        assert_eq!(&*entry.service, "global-test");
+    }
+}
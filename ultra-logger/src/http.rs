@@ -0,0 +1,324 @@
+//! Minimal HTTP/1.1 client shared by the network exporters
+//!
+//! One request per connection (`Connection: close`) over plain `http://`.
+//! That is all the exporters need to POST a batch and read the status and
+//! body back, without pulling a full HTTP stack into the logger.
+
+use crate::error::{LoggerError, Result};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Parsed `http://host[:port]/path` URL
+pub(crate) struct Endpoint {
+    url: String,
+    authority: String,
+    path: String,
+}
+
+impl Endpoint {
+    /// Parse `url`, using `default_path` when it has none
+    pub(crate) fn parse(url: &str, default_path: &str) -> Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            LoggerError::Transport(format!(
+                "unsupported endpoint {}: only http:// is supported",
+                url
+            ))
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, default_path),
+        };
+        let authority = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        };
+
+        Ok(Self {
+            url: url.to_string(),
+            authority,
+            path: path.to_string(),
+        })
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Send one POST to `path` and read the whole response
+    ///
+    /// `headers` are added after Host, Content-Length and Connection.
+    pub(crate) async fn post(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        timeout: Duration,
+    ) -> std::result::Result<Response, Attempt> {
+        let exchange = async {
+            let mut stream = TcpStream::connect(&self.authority).await?;
+            let mut head = format!(
+                "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                path,
+                self.authority,
+                body.len()
+            );
+            for (name, value) in headers {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str("\r\n");
+            stream.write_all(head.as_bytes()).await?;
+            stream.write_all(body).await?;
+
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await?;
+            Ok::<_, std::io::Error>(response)
+        };
+
+        match tokio::time::timeout(timeout, exchange).await {
+            Ok(Ok(raw)) => classify(&raw),
+            Ok(Err(e)) => Err(Attempt::Retry(e.to_string())),
+            Err(_) => Err(Attempt::Retry("timed out".to_string())),
+        }
+    }
+
+    /// [`post`](Self::post) with up to `max_retries` retries of transient
+    /// failures, waiting `backoff` before the first and doubling after, up
+    /// to [`MAX_BACKOFF`]
+    pub(crate) async fn post_with_retry(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        retry: &RetryPolicy,
+    ) -> std::result::Result<Response, String> {
+        let mut backoff = retry.backoff;
+        let mut attempt = 0;
+        loop {
+            match self.post(path, headers, body, retry.timeout).await {
+                Ok(response) => return Ok(response),
+                Err(Attempt::Retry(_)) if attempt < retry.max_retries => {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff = retry.next_backoff(backoff);
+                }
+                Err(Attempt::Retry(reason) | Attempt::Fail(reason)) => return Err(reason),
+            }
+        }
+    }
+}
+
+/// Longest wait between two attempts. Exporters retry inline on the
+/// processor, so a doubled backoff must not stall it for minutes; a
+/// configured `backoff` above this is used as is, without doubling.
+pub(crate) const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Retry settings for [`Endpoint::post_with_retry`]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_retries: u32,
+    pub(crate) backoff: Duration,
+    pub(crate) timeout: Duration,
+}
+
+impl RetryPolicy {
+    /// Wait after one of `backoff`: doubled, capped at [`MAX_BACKOFF`]
+    pub(crate) fn next_backoff(&self, backoff: Duration) -> Duration {
+        backoff.saturating_mul(2).min(MAX_BACKOFF.max(self.backoff))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Attempt {
+    Retry(String),
+    Fail(String),
+}
+
+/// Outcome of one exchange from the raw response bytes
+fn classify(raw: &[u8]) -> std::result::Result<Response, Attempt> {
+    match Response::parse(raw) {
+        Some(response) if (200..300).contains(&response.status) => Ok(response),
+        // Throttling and gateway errors are transient
+        Some(Response {
+            status: status @ (429 | 502 | 503 | 504),
+            ..
+        }) => Err(Attempt::Retry(format!("server returned {}", status))),
+        // The body usually says why the request was rejected
+        Some(response) => Err(Attempt::Fail(format!(
+            "server returned {}: {}",
+            response.status,
+            String::from_utf8_lossy(&response.body).trim()
+        ))),
+        None => Err(Attempt::Retry("malformed response".to_string())),
+    }
+}
+
+/// Status and de-chunked body of a response
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let split = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let head = std::str::from_utf8(&raw[..split]).ok()?;
+        let status = head.split_whitespace().nth(1)?.parse().ok()?;
+        let body = &raw[split + 4..];
+
+        let header = |wanted: &str| {
+            head.lines().skip(1).find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case(wanted)
+                    .then(|| value.trim())
+            })
+        };
+        let chunked =
+            header("transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+        let body = if chunked {
+            dechunk(body)?
+        } else if let Some(length) = header("content-length") {
+            // A body cut short means the connection dropped mid-response
+            body.get(..length.parse().ok()?)?.to_vec()
+        } else {
+            body.to_vec()
+        };
+        Some(Self { status, body })
+    }
+}
+
+/// Decode a `Transfer-Encoding: chunked` body
+fn dechunk(mut raw: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&raw[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(raw.get(..size)?);
+        if raw.get(size..size + 2)? != b"\r\n" {
+            return None;
+        }
+        raw = &raw[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(raw: &[u8]) -> std::result::Result<u16, Attempt> {
+        classify(raw).map(|response| response.status)
+    }
+
+    fn policy(backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            backoff,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let retry = policy(Duration::from_millis(100));
+        let mut backoff = retry.backoff;
+        let mut waits = Vec::new();
+        for _ in 0..10 {
+            backoff = retry.next_backoff(backoff);
+            waits.push(backoff.as_millis());
+        }
+        assert_eq!(
+            waits,
+            [200, 400, 800, 1600, 3200, 5000, 5000, 5000, 5000, 5000]
+        );
+
+        // A configured backoff above the cap stays as it is
+        let slow = policy(Duration::from_secs(30));
+        assert_eq!(slow.next_backoff(slow.backoff), Duration::from_secs(30));
+        assert_eq!(slow.next_backoff(Duration::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn chunked_body_is_reassembled() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n";
+        let response = Response::parse(raw).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"hello, world");
+    }
+
+    #[test]
+    fn content_length_bounds_the_body() {
+        let raw = b"HTTP/1.1 400 Bad Request\r\ncontent-length: 7\r\n\r\nbad doc trailing";
+        let response = Response::parse(raw).unwrap();
+        assert_eq!(response.body, b"bad doc");
+
+        let cut_short = b"HTTP/1.1 200 OK\r\nContent-Length: 20\r\n\r\npartial";
+        assert!(Response::parse(cut_short).is_none());
+    }
+
+    #[test]
+    fn body_without_framing_runs_to_the_end() {
+        let response = Response::parse(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        assert_eq!(response.status, 204);
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn malformed_responses_are_rejected() {
+        for raw in [
+            &b""[..],
+            b"HTTP/1.1 200 OK\r\nno blank line",
+            b"HTTP/1.1 OK\r\n\r\n",
+            b"\xff\xfe 200\r\n\r\n",
+            // Chunk shorter than its declared size
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\na\r\nshort\r\n0\r\n\r\n",
+            // Chunk not followed by CRLF
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n",
+            // Missing the terminating chunk
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+        ] {
+            assert!(
+                Response::parse(raw).is_none(),
+                "{:?}",
+                String::from_utf8_lossy(raw)
+            );
+            assert!(matches!(classify(raw), Err(Attempt::Retry(_))));
+        }
+    }
+
+    #[test]
+    fn only_throttling_and_gateway_errors_are_retried() {
+        for code in [200, 201, 204, 299] {
+            let raw = format!("HTTP/1.1 {} OK\r\nContent-Length: 0\r\n\r\n", code);
+            assert_eq!(status(raw.as_bytes()).unwrap(), code);
+        }
+        for code in [429, 502, 503, 504] {
+            let raw = format!("HTTP/1.1 {} Busy\r\n\r\n", code);
+            assert!(
+                matches!(status(raw.as_bytes()), Err(Attempt::Retry(_))),
+                "{}",
+                code
+            );
+        }
+        for code in [301, 400, 401, 403, 404, 413, 500, 501] {
+            let raw = format!("HTTP/1.1 {} No\r\n\r\nindex missing\n", code);
+            match status(raw.as_bytes()) {
+                Err(Attempt::Fail(reason)) => {
+                    assert_eq!(reason, format!("server returned {}: index missing", code))
+                }
+                other => panic!("{}: {:?}", code, other.map(|_| ())),
+            }
+        }
+    }
+}
@@ -0,0 +1,2590 @@
+//! Ultra-logger - simple, fast async logger for high-frequency trading
+//!
+//! Log calls push entries onto a channel and return immediately. A background
+//! processor groups them into batches, serializes each batch as NDJSON and
+//! hands it to a [`Transport`]: one of the built-ins selected with
+//! [`TransportKind`], or any custom implementation. The processor runs as a
+//! task on the current tokio runtime, or on a dedicated thread with its own
+//! runtime when the logger is built outside one.
+
+mod archive;
+pub mod clock;
+#[cfg(feature = "parquet")]
+mod columnar;
+pub mod compat;
+mod compress;
+pub mod config;
+pub mod correlation;
+mod elasticsearch;
+pub mod error;
+pub mod event;
+mod filter;
+mod format;
+mod global;
+mod http;
+mod log_adapter;
+mod loki;
+mod macros;
+mod otlp;
+mod partition;
+pub mod proto;
+mod retry;
+mod shm;
+mod spill;
+mod throttle;
+pub mod transport;
+
+pub use archive::{ArchiveConfig, ArchiveTransport};
+pub use clock::{Clock, SimulatedClock, SystemClock};
+#[cfg(feature = "parquet")]
+pub use columnar::{ParquetConfig, ParquetTransport};
+pub use compress::decompress;
+pub use config::{
+    BackpressurePolicy, BandwidthLimit, BufferConfig, ClockGuardConfig, CompressionAlgorithm,
+    CompressionConfig, ConnectionConfig, DrainOrder, LoggerConfig, RetryConfig, SamplingConfig,
+    SerializationFormat, TimestampSource, TransportConfig, TtlConfig,
+};
+pub use correlation::{CorrelationId, CORRELATION_ID_FIELD};
+pub use elasticsearch::{ElasticsearchConfig, ElasticsearchTransport};
+pub use error::{LoggerError, Result};
+pub use event::EventRegistry;
+pub use filter::{LevelSpec, LEVEL_ENV_VAR};
+pub use format::to_ndjson;
+pub use global::{global, init, log_global};
+pub use log_adapter::LogAdapter;
+pub use loki::{LokiConfig, LokiTransport};
+pub use otlp::{OtlpConfig, OtlpTransport};
+pub use partition::{
+    PartitionedLogger, PartitionedLoggerBuilder, DEFAULT_PARTITION, PARTITION_FIELD,
+};
+pub use shm::{ShmRingConfig, ShmRingReader, ShmRingTransport};
+pub use spill::RecoveryReport;
+#[cfg(unix)]
+pub use transport::UnixSocketTransport;
+pub use transport::{
+    FileTransport, FileTransportConfig, FsyncPolicy, StdoutTransport, Transport, TransportKind,
+};
+
+use chrono::{DateTime, Utc};
+use clock::ClockGuard;
+pub use compact_str::CompactString;
+use compress::Compressor;
+use filter::LevelFilter;
+use flume::TrySendError;
+use retry::{FailedBatch, RetryQueue};
+use serde::{Deserialize, Serialize};
+use spill::SpillFile;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+use throttle::TokenBucket;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Maximum entries per batch
+const BATCH_SIZE: usize = 1000;
+
+/// Maximum time an entry waits in a partial batch
+const BATCH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Log severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl FromStr for LogLevel {
+    type Err = LoggerError;
+
+    /// Case-insensitive; accepts `warning` for Warn
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(LoggerError::InvalidLevel(s.to_string())),
+        }
+    }
+}
+
+/// Structured field value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LogValue {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    String(String),
+}
+
+impl From<&str> for LogValue {
+    fn from(value: &str) -> Self {
+        LogValue::String(value.to_string())
+    }
+}
+
+impl From<String> for LogValue {
+    fn from(value: String) -> Self {
+        LogValue::String(value)
+    }
+}
+
+impl From<bool> for LogValue {
+    fn from(value: bool) -> Self {
+        LogValue::Bool(value)
+    }
+}
+
+impl From<i32> for LogValue {
+    fn from(value: i32) -> Self {
+        LogValue::I64(value.into())
+    }
+}
+
+impl From<i64> for LogValue {
+    fn from(value: i64) -> Self {
+        LogValue::I64(value)
+    }
+}
+
+impl From<u32> for LogValue {
+    fn from(value: u32) -> Self {
+        LogValue::U64(value.into())
+    }
+}
+
+impl From<u64> for LogValue {
+    fn from(value: u64) -> Self {
+        LogValue::U64(value)
+    }
+}
+
+impl From<usize> for LogValue {
+    fn from(value: usize) -> Self {
+        LogValue::U64(value as u64)
+    }
+}
+
+impl From<f64> for LogValue {
+    fn from(value: f64) -> Self {
+        LogValue::F64(value)
+    }
+}
+
+/// A single log record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// When the entry was logged
+    pub timestamp: DateTime<Utc>,
+    pub level: LogLevel,
+
+    /// Shared with the logger handle; stamping it costs a reference count
+    pub service: Arc<str>,
+
+    /// Stored inline up to 24 bytes; a literal is borrowed, a `String` is
+    /// kept without copying
+    pub message: CompactString,
+
+    /// When the logged event happened, if the caller said so (e.g. an
+    /// exchange timestamp, or a backfilled record)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<DateTime<Utc>>,
+
+    /// Stable identifier of what happened, for alerting and routing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<String>,
+
+    /// Stable error code, usually alongside an Error-level entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+
+    /// Structured fields; omitted from output when empty
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, LogValue>,
+}
+
+impl LogEntry {
+    /// The entry's time as chosen by `source`
+    pub fn time(&self, source: TimestampSource) -> DateTime<Utc> {
+        match source {
+            TimestampSource::Logged => self.timestamp,
+            TimestampSource::Event => self.event_time.unwrap_or(self.timestamp),
+        }
+    }
+}
+
+/// Snapshot of logger counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggerStats {
+    /// Entries accepted onto the queue (or spill file)
+    pub messages_logged: u64,
+
+    /// Entries rejected after shutdown, shed by the backpressure policy,
+    /// expired, or lost to serialization/transport errors
+    pub messages_dropped: u64,
+
+    /// Entries skipped by sampling
+    pub sampled_out: u64,
+
+    /// Entries dropped for outliving their level's TTL
+    pub expired: u64,
+
+    /// Log calls that had to wait for queue space (`Block`)
+    pub queue_full_waits: u64,
+
+    /// Entries discarded because the queue was full (`DropNewest`, or `Block`
+    /// for global macro calls made inside a tokio runtime)
+    pub dropped_newest: u64,
+
+    /// Queued entries evicted to make room (`DropOldest`)
+    pub dropped_oldest: u64,
+
+    /// Entries written to the spill file (`SpillToDisk`)
+    pub spilled: u64,
+
+    /// Spilled entries read back into the pipeline
+    pub spill_recovered: u64,
+
+    /// Times a backlog was written newest first (`DrainOrder::LifoOverThreshold`)
+    pub lifo_drains: u64,
+
+    /// Entries written as part of a newest-first drain, i.e. possibly out of order
+    pub reordered: u64,
+
+    /// Batches handed to the transport
+    pub batches_flushed: u64,
+
+    /// Bytes handed to the transport
+    pub bytes_written: u64,
+
+    /// Failed transport writes, including failed resends
+    pub transport_errors: u64,
+
+    /// Resends of failed batches (`RetryConfig`)
+    pub retried: u64,
+
+    /// Batches written to the dead-letter file after exhausting retries
+    pub dead_lettered: u64,
+
+    /// Time spent in the call-site level filter, sampling and event-code
+    /// checks, sampled on one log call in [`STAGE_TIMING_SAMPLE`] per thread
+    pub filter: StageTiming,
+
+    /// Time spent building kept entries (context fields, timestamp), sampled
+    /// like `filter`
+    pub enrichment: StageTiming,
+
+    /// Time spent serializing batches
+    pub serialization: StageTiming,
+
+    /// Time spent handing batches to the transport
+    pub transport: StageTiming,
+
+    /// Time spent waiting on the bandwidth limit, sampled per delayed batch
+    pub throttle: StageTiming,
+
+    /// Time spent compressing, sampled per compressed batch
+    pub compression: StageTiming,
+
+    /// Serialized bytes that went into compression
+    pub compression_input_bytes: u64,
+
+    /// Compressed bytes that came out
+    pub compression_output_bytes: u64,
+
+    /// Delivery lag per level, indexed by `LogLevel as usize`: from each
+    /// entry's event time (or logged time) to the transport accepting it
+    pub delivery_lag: [LagHistogram; 4],
+
+    /// Wall-clock jumps detected (`ClockGuardConfig`)
+    pub clock_jumps: u64,
+}
+
+/// Upper bounds of the stage-timing buckets in microseconds; one more bucket
+/// holds everything slower
+pub const STAGE_BUCKET_BOUNDS_US: [u64; 15] = [
+    1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 100_000,
+];
+
+/// Log calls per thread for each one whose call-site stages are timed, so
+/// most calls don't read the clock
+pub const STAGE_TIMING_SAMPLE: u32 = 64;
+
+/// Time accounting for one pipeline stage: once per batch for the processor
+/// stages, per sampled call for the call-site ones
+///
+/// ```
+/// # use ultra_logger::LoggerStats;
+/// # let stats = LoggerStats::default();
+/// // Which stage blew the budget?
+/// for (stage, timing) in [("filter", stats.filter), ("transport", stats.transport)] {
+///     println!("{}: p99 {:?}, max {:?}", stage, timing.quantile(0.99), timing.max());
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageTiming {
+    /// Times the stage was measured
+    pub samples: u64,
+
+    /// Total time spent in the stage
+    pub total_nanos: u64,
+
+    /// Slowest single measurement
+    pub max_nanos: u64,
+
+    /// Measurements per bucket: `counts[i]` holds times up to
+    /// `STAGE_BUCKET_BOUNDS_US[i]` (and above the previous bound); the last
+    /// holds times above every bound
+    pub counts: [u64; STAGE_BUCKET_BOUNDS_US.len() + 1],
+}
+
+impl LoggerStats {
+    /// Serialized bytes per compressed byte over all compressed batches
+    /// (0.0 before any batch was compressed)
+    pub fn compression_ratio(&self) -> f64 {
+        if self.compression_output_bytes == 0 {
+            return 0.0;
+        }
+        self.compression_input_bytes as f64 / self.compression_output_bytes as f64
+    }
+}
+
+impl StageTiming {
+    /// Mean time per batch
+    pub fn average(&self) -> Duration {
+        if self.samples == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.total_nanos / self.samples)
+    }
+
+    /// Slowest single measurement
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos)
+    }
+
+    /// Upper bound of the bucket containing quantile `q` (0.0 to 1.0); the
+    /// slowest measurement when that is lower, or in the overflow bucket
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let max_us = self.max_nanos.div_ceil(1_000);
+        bucket_quantile(&STAGE_BUCKET_BOUNDS_US, &self.counts, max_us, q)
+            .map(|us| Duration::from_nanos((us * 1_000).min(self.max_nanos)))
+    }
+}
+
+/// Bucket index for `value` under ascending `bounds`
+fn bucket(bounds: &[u64], value: u64) -> usize {
+    bounds.partition_point(|bound| *bound < value)
+}
+
+/// Upper bound of the bucket holding quantile `q`, capped at `max`; `counts`
+/// has one more entry than `bounds`, for values above them all
+fn bucket_quantile(bounds: &[u64], counts: &[u64], max: u64, q: f64) -> Option<u64> {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+    let mut seen = 0;
+    for (i, count) in counts.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return Some(bounds.get(i).copied().unwrap_or(max).min(max));
+        }
+    }
+    Some(max)
+}
+
+/// Upper bounds of the delivery-lag buckets in milliseconds; one more bucket
+/// holds everything slower
+pub const LAG_BUCKET_BOUNDS_MS: [u64; 13] = [
+    1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_000, 5_000, 10_000,
+];
+
+/// Distribution of delivery lag for one level
+///
+/// ```
+/// # use std::time::Duration;
+/// # use ultra_logger::{LogLevel, LoggerStats};
+/// # let stats = LoggerStats::default();
+/// // "99% of Error logs delivered within 2 seconds"
+/// let error_lag = &stats.delivery_lag[LogLevel::Error as usize];
+/// let met = error_lag.count() == 0 || error_lag.fraction_within(Duration::from_secs(2)) >= 0.99;
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LagHistogram {
+    /// Entries per bucket: `counts[i]` holds lags up to
+    /// `LAG_BUCKET_BOUNDS_MS[i]` (and above the previous bound); the last
+    /// holds lags above every bound
+    pub counts: [u64; LAG_BUCKET_BOUNDS_MS.len() + 1],
+
+    /// Largest single lag
+    pub max_ms: u64,
+}
+
+impl LagHistogram {
+    /// Entries recorded
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Share of entries delivered within `limit`, counting whole buckets, so
+    /// exact when `limit` is one of the bucket bounds
+    pub fn fraction_within(&self, limit: Duration) -> f64 {
+        let total = self.count();
+        if total == 0 {
+            return 0.0;
+        }
+        let limit_ms = limit.as_millis();
+        let within: u64 = LAG_BUCKET_BOUNDS_MS
+            .iter()
+            .zip(&self.counts)
+            .take_while(|(bound, _)| u128::from(**bound) <= limit_ms)
+            .map(|(_, count)| count)
+            .sum();
+        within as f64 / total as f64
+    }
+
+    /// Upper bound of the bucket containing quantile `q` (0.0 to 1.0); the
+    /// largest lag when that is the overflow bucket
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        bucket_quantile(&LAG_BUCKET_BOUNDS_MS, &self.counts, self.max_ms, q)
+            .map(Duration::from_millis)
+    }
+}
+
+#[derive(Default)]
+struct StatsCounters {
+    messages_logged: AtomicU64,
+    messages_dropped: AtomicU64,
+    sampled_out: AtomicU64,
+    expired: AtomicU64,
+    queue_full_waits: AtomicU64,
+    dropped_newest: AtomicU64,
+    dropped_oldest: AtomicU64,
+    spilled: AtomicU64,
+    spill_recovered: AtomicU64,
+    lifo_drains: AtomicU64,
+    reordered: AtomicU64,
+    batches_flushed: AtomicU64,
+    bytes_written: AtomicU64,
+    transport_errors: AtomicU64,
+    retried: AtomicU64,
+    dead_lettered: AtomicU64,
+    filter: StageCounters,
+    enrichment: StageCounters,
+    serialization: StageCounters,
+    transport: StageCounters,
+    throttle: StageCounters,
+    compression: StageCounters,
+    compression_input_bytes: AtomicU64,
+    compression_output_bytes: AtomicU64,
+    delivery_lag: [LagCounters; 4],
+    clock_jumps: AtomicU64,
+}
+
+#[derive(Default)]
+struct LagCounters {
+    counts: [AtomicU64; LAG_BUCKET_BOUNDS_MS.len() + 1],
+    max_ms: AtomicU64,
+}
+
+impl LagCounters {
+    fn record(&self, lag_ms: u64) {
+        self.counts[bucket(&LAG_BUCKET_BOUNDS_MS, lag_ms)].fetch_add(1, Ordering::Relaxed);
+        self.max_ms.fetch_max(lag_ms, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LagHistogram {
+        LagHistogram {
+            counts: std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed)),
+            max_ms: self.max_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+struct StageCounters {
+    samples: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    counts: [AtomicU64; STAGE_BUCKET_BOUNDS_US.len() + 1],
+}
+
+impl StageCounters {
+    fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        let micros = nanos.div_ceil(1_000);
+        self.counts[bucket(&STAGE_BUCKET_BOUNDS_US, micros)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> StageTiming {
+        StageTiming {
+            samples: self.samples.load(Ordering::Relaxed),
+            total_nanos: self.total_nanos.load(Ordering::Relaxed),
+            max_nanos: self.max_nanos.load(Ordering::Relaxed),
+            counts: std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Whether this call's call-site stages are timed, one in
+/// [`STAGE_TIMING_SAMPLE`] per thread
+fn time_this_call() -> bool {
+    thread_local! {
+        static CALLS: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+    }
+    CALLS.with(|calls| {
+        let n = calls.get();
+        calls.set(n.wrapping_add(1));
+        n % STAGE_TIMING_SAMPLE == 0
+    })
+}
+
+impl StatsCounters {
+    fn snapshot(&self) -> LoggerStats {
+        LoggerStats {
+            messages_logged: self.messages_logged.load(Ordering::Relaxed),
+            messages_dropped: self.messages_dropped.load(Ordering::Relaxed),
+            sampled_out: self.sampled_out.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+            queue_full_waits: self.queue_full_waits.load(Ordering::Relaxed),
+            dropped_newest: self.dropped_newest.load(Ordering::Relaxed),
+            dropped_oldest: self.dropped_oldest.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+            spill_recovered: self.spill_recovered.load(Ordering::Relaxed),
+            lifo_drains: self.lifo_drains.load(Ordering::Relaxed),
+            reordered: self.reordered.load(Ordering::Relaxed),
+            batches_flushed: self.batches_flushed.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            transport_errors: self.transport_errors.load(Ordering::Relaxed),
+            retried: self.retried.load(Ordering::Relaxed),
+            dead_lettered: self.dead_lettered.load(Ordering::Relaxed),
+            filter: self.filter.snapshot(),
+            enrichment: self.enrichment.snapshot(),
+            serialization: self.serialization.snapshot(),
+            transport: self.transport.snapshot(),
+            throttle: self.throttle.snapshot(),
+            compression: self.compression.snapshot(),
+            compression_input_bytes: self.compression_input_bytes.load(Ordering::Relaxed),
+            compression_output_bytes: self.compression_output_bytes.load(Ordering::Relaxed),
+            delivery_lag: std::array::from_fn(|i| self.delivery_lag[i].snapshot()),
+            clock_jumps: self.clock_jumps.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Deterministic 1-in-N sampling per level
+struct Sampler {
+    keep_one_in: [u64; 4],
+    seen: [AtomicU64; 4],
+}
+
+impl Sampler {
+    fn new(config: SamplingConfig) -> Self {
+        Self {
+            keep_one_in: [config.debug, config.info, config.warn, config.error]
+                .map(|n| u64::from(n.max(1))),
+            seen: Default::default(),
+        }
+    }
+
+    fn keep(&self, level: LogLevel) -> bool {
+        let index = level as usize;
+        let n = self.keep_one_in[index];
+        n == 1
+            || self.seen[index]
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(n)
+    }
+}
+
+enum Command {
+    Log(LogEntry),
+    /// Barrier: acknowledged once everything queued before it reached the transport
+    Flush(flume::Sender<Result<()>>),
+    Shutdown,
+}
+
+/// Where the background processor runs
+enum Worker {
+    /// Task on the runtime the logger was created in
+    Task(JoinHandle<()>),
+
+    /// Dedicated thread with its own runtime; signals on this channel when done
+    Thread(flume::Receiver<()>),
+}
+
+/// Async logger with a background batching processor
+///
+/// Cloning a logger, or creating a [`child`](UltraLogger::child), gives another
+/// handle onto the same queue and background processor.
+#[derive(Clone)]
+pub struct UltraLogger {
+    service: Arc<str>,
+    /// Fields stamped on every entry from this handle
+    context: Arc<BTreeMap<String, LogValue>>,
+    pipeline: Arc<Pipeline>,
+}
+
+/// Queue, policies and worker shared by a logger and its children
+struct Pipeline {
+    filter: LevelFilter,
+    sampler: Sampler,
+    events: Option<EventRegistry>,
+    sender: flume::Sender<Command>,
+    backpressure: BackpressurePolicy,
+    /// Receiving side kept by producers to evict entries under `DropOldest`
+    evict: Option<flume::Receiver<Command>>,
+    spill: Option<Arc<SpillFile>>,
+    /// What opening the spill file found from a previous run
+    recovery: Option<RecoveryReport>,
+    closed: AtomicBool,
+    stats: Arc<StatsCounters>,
+    clock: Option<Arc<ClockGuard>>,
+    worker: Mutex<Option<Worker>>,
+}
+
+impl UltraLogger {
+    /// Create a logger writing to stdout
+    ///
+    /// Inside a tokio runtime the background processor runs as a task on it;
+    /// otherwise it gets a dedicated thread. Panics only if that thread cannot
+    /// be started; use [`UltraLogger::builder`] to get a `Result` instead.
+    pub fn new(service: String) -> Self {
+        Self::new_with_transport(service, Box::new(StdoutTransport::new()))
+    }
+
+    /// Create a logger writing to a custom transport
+    ///
+    /// Runs the background processor like [`UltraLogger::new`].
+    pub fn new_with_transport(service: String, transport: Box<dyn Transport>) -> Self {
+        Self::builder()
+            .with_service(service)
+            .with_custom_transport(transport)
+            .build()
+            .expect("failed to start ultra-logger background thread")
+    }
+
+    fn spawn(settings: LoggerSettings, transport: Box<dyn Transport>) -> Result<Self> {
+        let LoggerSettings {
+            service,
+            level,
+            service_levels,
+            buffer,
+            sampling,
+            ttl,
+            bandwidth,
+            retry,
+            compression,
+            format,
+            stats_interval,
+            clock_guard,
+            clock,
+            context,
+            events,
+        } = settings;
+
+        let (sender, receiver) = flume::bounded(buffer.capacity.max(1));
+        let stats = Arc::new(StatsCounters::default());
+        let clock = clock_guard.map(|config| {
+            let clock = clock.unwrap_or_else(|| Arc::new(SystemClock::default()));
+            Arc::new(ClockGuard::new(config, clock, Arc::clone(&stats)))
+        });
+
+        let (spill, recovery) = match &buffer.backpressure {
+            BackpressurePolicy::SpillToDisk { path } => {
+                let (spill, report) = SpillFile::open(path)?;
+                (Some(Arc::new(spill)), Some(report))
+            }
+            _ => (None, None),
+        };
+        let evict = match buffer.backpressure {
+            BackpressurePolicy::DropOldest => Some(receiver.clone()),
+            _ => None,
+        };
+
+        let processor = BackgroundProcessor {
+            transport,
+            spill: spill.clone(),
+            bandwidth: bandwidth.map(TokenBucket::new),
+            lifo_threshold: match buffer.drain_order {
+                DrainOrder::Fifo => None,
+                DrainOrder::LifoOverThreshold { threshold } => Some(threshold.max(1)),
+            },
+            ttl: [ttl.debug, ttl.info, ttl.warn, ttl.error].map(|ttl| {
+                ttl.map(|ttl| chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX))
+            }),
+            stats: Arc::clone(&stats),
+            buffer: Vec::new(),
+            in_flight: Vec::new(),
+            retry: retry.map(RetryQueue::new),
+            next_seq: 0,
+            compressor: Compressor::new(compression),
+            compressed: Vec::new(),
+            format,
+            stats_report: stats_interval.map(|interval| StatsReport::new(&service, interval)),
+            clock: clock.clone(),
+        };
+
+        let worker = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => Worker::Task(handle.spawn(processor.run(receiver))),
+            Err(_) => Self::spawn_thread(processor, receiver)?,
+        };
+
+        let pipeline = Pipeline {
+            filter: LevelFilter::new(level, service_levels),
+            sampler: Sampler::new(sampling),
+            events,
+            sender,
+            backpressure: buffer.backpressure,
+            evict,
+            spill,
+            recovery,
+            closed: AtomicBool::new(false),
+            stats,
+            clock,
+            worker: Mutex::new(Some(worker)),
+        };
+        let logger = Self {
+            service: service.into(),
+            context: Arc::new(context),
+            pipeline: Arc::new(pipeline),
+        };
+        logger.log_recovery()?;
+        Ok(logger)
+    }
+
+    /// Log what the spill file held from a previous run, if anything
+    fn log_recovery(&self) -> Result<()> {
+        let Some(report) = self.recovery_report().filter(|report| !report.is_empty()) else {
+            return Ok(());
+        };
+        let level = if report.is_clean() {
+            LogLevel::Info
+        } else {
+            LogLevel::Warn
+        };
+        let corrupted_bytes: u64 = report
+            .corrupted
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum();
+        self.log_blocking(
+            level,
+            "spill file recovered".to_string(),
+            &[
+                ("path", LogValue::from(report.path.display().to_string())),
+                (
+                    "entries_recovered",
+                    LogValue::from(report.entries_recovered),
+                ),
+                (
+                    "corrupted_lines",
+                    LogValue::from(report.corrupted.len() as u64),
+                ),
+                ("corrupted_bytes", LogValue::from(corrupted_bytes)),
+                ("truncated_bytes", LogValue::from(report.truncated_bytes)),
+            ],
+        )
+    }
+
+    /// Run the processor on its own current-thread runtime
+    fn spawn_thread(
+        processor: BackgroundProcessor,
+        receiver: flume::Receiver<Command>,
+    ) -> Result<Worker> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let (done_tx, done_rx) = flume::bounded(1);
+
+        std::thread::Builder::new()
+            .name("ultra-logger".to_string())
+            .spawn(move || {
+                runtime.block_on(processor.run(receiver));
+                let _ = done_tx.send(());
+            })?;
+
+        Ok(Worker::Thread(done_rx))
+    }
+
+    /// Start configuring a logger
+    pub fn builder() -> UltraLoggerBuilder {
+        UltraLoggerBuilder::default()
+    }
+
+    /// Service name stamped on every entry
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    /// Handle that shares this logger's pipeline but stamps a different service
+    ///
+    /// Children cost a few reference-count bumps; there is no extra queue or
+    /// task. They inherit this handle's fields. Shutting down any handle shuts
+    /// down the shared pipeline.
+    pub fn child(&self, service: impl Into<Arc<str>>) -> UltraLogger {
+        UltraLogger {
+            service: service.into(),
+            context: Arc::clone(&self.context),
+            pipeline: Arc::clone(&self.pipeline),
+        }
+    }
+
+    /// Handle that stamps an extra field on every entry
+    ///
+    /// The field is added on top of this handle's existing ones; the original
+    /// handle is unchanged.
+    pub fn with_field(&self, key: impl Into<String>, value: impl Into<LogValue>) -> UltraLogger {
+        let mut context = (*self.context).clone();
+        context.insert(key.into(), value.into());
+        UltraLogger {
+            service: Arc::clone(&self.service),
+            context: Arc::new(context),
+            pipeline: Arc::clone(&self.pipeline),
+        }
+    }
+
+    /// Handle that tags every entry with a workflow's correlation ID
+    ///
+    /// Pass the returned handle along the workflow, or send the ID to other
+    /// services and attach it there with the ID parsed from the wire.
+    pub fn with_correlation_id(&self, id: CorrelationId) -> UltraLogger {
+        self.with_field(CORRELATION_ID_FIELD, id.to_string())
+    }
+
+    /// Queue an entry at the given level
+    ///
+    /// Entries below the minimum level or skipped by sampling return `Ok`
+    /// without allocating anything. When the queue is full the configured
+    /// [`BackpressurePolicy`] decides whether this waits, sheds an entry or
+    /// spills to disk. Shed entries are counted in [`LoggerStats`] rather than
+    /// reported as errors.
+    pub async fn log(&self, level: LogLevel, message: String) -> Result<()> {
+        self.submit(level, message, None, None, &[]).await
+    }
+
+    /// Queue an entry without allocating for the message when it is a
+    /// literal, and without copying it when it is an owned `String`
+    ///
+    /// With a `'static` message the only allocation left on the calling side
+    /// is the queue's; prefer this over [`log`](UltraLogger::log) on hot
+    /// paths that log fixed text.
+    pub async fn log_message(
+        &self,
+        level: LogLevel,
+        message: impl Into<Cow<'static, str>>,
+    ) -> Result<()> {
+        let message = match message.into() {
+            Cow::Borrowed(message) => CompactString::const_new(message),
+            Cow::Owned(message) => CompactString::from(message),
+        };
+        self.submit(level, message, None, None, &[]).await
+    }
+
+    /// Queue an entry with a borrowed message, copied only if the entry
+    /// passes the level filter and sampling
+    ///
+    /// Messages up to 24 bytes are stored inline without allocating.
+    pub async fn log_str(&self, level: LogLevel, message: &str) -> Result<()> {
+        self.submit(level, message, None, None, &[]).await
+    }
+
+    /// Queue an entry with structured fields
+    ///
+    /// Fields are merged over the logger's context, so a field with the same
+    /// key as a context value wins. The [`info_kv!`] family of macros wraps
+    /// this for the common levels.
+    pub async fn log_with_fields(
+        &self,
+        level: LogLevel,
+        message: String,
+        fields: &[(&str, LogValue)],
+    ) -> Result<()> {
+        self.submit(level, message, None, None, fields).await
+    }
+
+    /// Queue an entry tagged with a stable event ID
+    ///
+    /// The ID must be well-formed (see [`event::validate_code`]) and, when the
+    /// logger has an [`EventRegistry`], registered; otherwise this returns
+    /// [`LoggerError::InvalidEventCode`] and nothing is logged.
+    pub async fn log_event(&self, level: LogLevel, event_id: &str, message: String) -> Result<()> {
+        self.submit(level, message, Some(event_id), None, &[]).await
+    }
+
+    /// Queue an Error entry tagged with a stable error code
+    ///
+    /// Validated the same way as [`log_event`](UltraLogger::log_event).
+    pub async fn error_with_code(&self, error_code: &str, message: String) -> Result<()> {
+        self.submit(LogLevel::Error, message, None, Some(error_code), &[])
+            .await
+    }
+
+    /// Queue an entry for an event that happened at `event_time`
+    ///
+    /// The entry's `timestamp` is still when it was logged; sinks configured
+    /// with [`TimestampSource::Event`] order and index by `event_time`
+    /// instead, so delayed or backfilled records land where they belong.
+    pub async fn log_at(
+        &self,
+        level: LogLevel,
+        event_time: DateTime<Utc>,
+        message: String,
+        fields: &[(&str, LogValue)],
+    ) -> Result<()> {
+        match self.prepare(level, message, None, None, fields)? {
+            Some(mut entry) => {
+                entry.event_time = Some(event_time);
+                self.pipeline.enqueue(entry, true).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn submit(
+        &self,
+        level: LogLevel,
+        message: impl Into<CompactString>,
+        event_id: Option<&str>,
+        error_code: Option<&str>,
+        fields: &[(&str, LogValue)],
+    ) -> Result<()> {
+        match self.prepare(level, message, event_id, error_code, fields)? {
+            Some(entry) => self.pipeline.enqueue(entry, true).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Queue an entry from synchronous code
+    ///
+    /// Same as [`log_with_fields`](UltraLogger::log_with_fields), except that
+    /// under `Block` a full queue blocks the calling thread, so avoid it on the
+    /// thread of a current-thread runtime that also runs the processor.
+    pub fn log_blocking(
+        &self,
+        level: LogLevel,
+        message: String,
+        fields: &[(&str, LogValue)],
+    ) -> Result<()> {
+        match self.prepare(level, message, None, None, fields)? {
+            Some(entry) => block_on(self.pipeline.enqueue(entry, true)),
+            None => Ok(()),
+        }
+    }
+
+    /// Queue an entry from synchronous code that may be on a runtime thread
+    ///
+    /// Same as [`log_blocking`](UltraLogger::log_blocking), except that when
+    /// called inside a tokio runtime a full queue under `Block` drops the
+    /// entry as `DropNewest` would: parking a runtime thread can stall the
+    /// processor it is waiting on.
+    pub(crate) fn log_nonblocking(&self, level: LogLevel, message: String) -> Result<()> {
+        match self.prepare(level, message, None, None, &[])? {
+            Some(entry) => {
+                let may_wait = tokio::runtime::Handle::try_current().is_err();
+                block_on(self.pipeline.enqueue(entry, may_wait))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Whether an entry at `level` from this handle would pass the level filter
+    pub(crate) fn enabled(&self, level: LogLevel) -> bool {
+        self.pipeline.filter.enabled(&self.service, level)
+    }
+
+    /// Run the pre-queue checks and build the entry; `None` means discarded
+    ///
+    /// The message is only converted once the entry is known to be kept.
+    fn prepare(
+        &self,
+        level: LogLevel,
+        message: impl Into<CompactString>,
+        event_id: Option<&str>,
+        error_code: Option<&str>,
+        fields: &[(&str, LogValue)],
+    ) -> Result<Option<LogEntry>> {
+        let started = time_this_call().then(std::time::Instant::now);
+        let accepted = self.pipeline.accepts(&self.service, level)?
+            && self.pipeline.accepts_codes(event_id, error_code)?;
+        let filtered = started.map(|started| {
+            let now = std::time::Instant::now();
+            self.pipeline.stats.filter.record(now - started);
+            now
+        });
+        if !accepted {
+            return Ok(None);
+        }
+
+        let mut all_fields = (*self.context).clone();
+        for (key, value) in fields {
+            all_fields.insert((*key).to_string(), value.clone());
+        }
+
+        let timestamp = match &self.pipeline.clock {
+            Some(clock) => {
+                let stamp = clock.stamp();
+                let millis = |nanos: i64| LogValue::F64(nanos as f64 / 1e6);
+                if let Some(jump) = stamp.jump {
+                    all_fields.insert("clock_jump_ms".to_string(), millis(jump));
+                }
+                if let Some(adjust) = stamp.adjust {
+                    all_fields.insert("clock_adjust_ms".to_string(), millis(adjust));
+                }
+                stamp.time
+            }
+            None => Utc::now(),
+        };
+
+        let entry = LogEntry {
+            timestamp,
+            level,
+            service: Arc::clone(&self.service),
+            message: message.into(),
+            event_time: None,
+            event_id: event_id.map(str::to_string),
+            error_code: error_code.map(str::to_string),
+            fields: all_fields,
+        };
+        if let Some(filtered) = filtered {
+            self.pipeline.stats.enrichment.record(filtered.elapsed());
+        }
+        Ok(Some(entry))
+    }
+
+    /// Queue an entry at Debug level
+    pub async fn debug(&self, message: String) -> Result<()> {
+        self.log(LogLevel::Debug, message).await
+    }
+
+    /// Queue an entry at Info level
+    pub async fn info(&self, message: String) -> Result<()> {
+        self.log(LogLevel::Info, message).await
+    }
+
+    /// Queue an entry at Warn level
+    pub async fn warn(&self, message: String) -> Result<()> {
+        self.log(LogLevel::Warn, message).await
+    }
+
+    /// Queue an entry at Error level
+    pub async fn error(&self, message: String) -> Result<()> {
+        self.log(LogLevel::Error, message).await
+    }
+
+    /// Minimum level for services without a temporary override
+    pub fn level(&self) -> LogLevel {
+        self.pipeline.filter.base()
+    }
+
+    /// Change the minimum level at runtime
+    ///
+    /// Applies to every handle on this pipeline. Entries below it are dropped
+    /// before an entry is allocated; per-service levels and temporary
+    /// overrides still take precedence.
+    pub fn set_level(&self, level: LogLevel) {
+        self.pipeline.filter.set_base(level);
+    }
+
+    /// Give `service` its own minimum level until it is cleared
+    pub fn set_service_level(&self, service: &str, level: LogLevel) {
+        self.pipeline.filter.set_service_level(service, level);
+    }
+
+    /// Put `service` back on the default minimum level
+    pub fn clear_service_level(&self, service: &str) {
+        self.pipeline.filter.clear_service_level(service);
+    }
+
+    /// Replace the default level (if the spec has one) and all per-service
+    /// levels, e.g. after re-reading [`LEVEL_ENV_VAR`]
+    pub fn apply_level_spec(&self, spec: &LevelSpec) {
+        self.pipeline.filter.apply_spec(spec);
+    }
+
+    /// Use `level` as the minimum for `service` for the next `duration`, then
+    /// revert automatically
+    ///
+    /// Meant for debug windows during an incident, e.g. capturing Debug from
+    /// the execution gateway for ten minutes. Applies to every handle on this
+    /// pipeline whose service name matches; a new override for the same
+    /// service replaces the old one.
+    pub fn override_level(&self, service: &str, level: LogLevel, duration: Duration) {
+        self.pipeline.filter.set_override(service, level, duration);
+    }
+
+    /// End a temporary override before it expires
+    pub fn clear_level_override(&self, service: &str) {
+        self.pipeline.filter.clear_override(service);
+    }
+
+    /// Wait until every entry queued before this call has been serialized,
+    /// handed to the transport and the transport flushed
+    ///
+    /// The flush request queues behind pending entries regardless of the
+    /// backpressure policy, so under `Block` it waits like a log call would.
+    pub async fn flush(&self) -> Result<()> {
+        self.pipeline.flush().await
+    }
+
+    /// Current counters
+    pub fn stats(&self) -> LoggerStats {
+        self.pipeline.stats.snapshot()
+    }
+
+    /// What the spill file held from a previous run (`SpillToDisk` only)
+    ///
+    /// Intact entries are replayed once the queue is idle; the report is
+    /// also logged at startup, at Warn if anything was skipped or truncated.
+    pub fn recovery_report(&self) -> Option<&RecoveryReport> {
+        self.pipeline.recovery.as_ref()
+    }
+
+    /// Write out everything queued so far and stop the background processor
+    ///
+    /// Entries logged after shutdown, through this or any other handle on the
+    /// same pipeline, are rejected with [`LoggerError::ChannelClosed`].
+    pub async fn shutdown(&self) -> Result<()> {
+        self.pipeline.shutdown().await
+    }
+}
+
+impl Pipeline {
+    /// Level, sampling and shutdown checks done before building an entry
+    fn accepts(&self, service: &str, level: LogLevel) -> Result<bool> {
+        if self.closed.load(Ordering::Acquire) {
+            self.stats.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Err(LoggerError::ChannelClosed);
+        }
+        if !self.filter.enabled(service, level) {
+            return Ok(false);
+        }
+        if !self.sampler.keep(level) {
+            self.stats.sampled_out.fetch_add(1, Ordering::Relaxed);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Code validation and muting; only runs for entries that carry a code
+    fn accepts_codes(&self, event_id: Option<&str>, error_code: Option<&str>) -> Result<bool> {
+        if event_id.is_none() && error_code.is_none() {
+            return Ok(true);
+        }
+        for code in [event_id, error_code].into_iter().flatten() {
+            event::validate_code(code)?;
+        }
+        match &self.events {
+            Some(registry) => registry.check(event_id, error_code),
+            None => Ok(true),
+        }
+    }
+
+    /// Queue an entry; `may_wait: false` turns `Block` into `DropNewest`
+    async fn enqueue(&self, entry: LogEntry, may_wait: bool) -> Result<()> {
+        let command = match self.sender.try_send(Command::Log(entry)) {
+            Ok(()) => {
+                self.stats.messages_logged.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                self.stats.messages_dropped.fetch_add(1, Ordering::Relaxed);
+                return Err(LoggerError::ChannelClosed);
+            }
+            Err(TrySendError::Full(command)) => command,
+        };
+
+        let result = self.on_queue_full(command, may_wait).await;
+        match result {
+            Ok(true) => self.stats.messages_logged.fetch_add(1, Ordering::Relaxed),
+            Ok(false) | Err(_) => self.stats.messages_dropped.fetch_add(1, Ordering::Relaxed),
+        };
+        result.map(|_| ())
+    }
+
+    /// Apply the backpressure policy; returns whether the entry was kept
+    async fn on_queue_full(&self, command: Command, may_wait: bool) -> Result<bool> {
+        match &self.backpressure {
+            BackpressurePolicy::Block if may_wait => {
+                self.stats.queue_full_waits.fetch_add(1, Ordering::Relaxed);
+                self.sender
+                    .send_async(command)
+                    .await
+                    .map(|_| true)
+                    .map_err(|_| LoggerError::ChannelClosed)
+            }
+            BackpressurePolicy::Block | BackpressurePolicy::DropNewest => {
+                self.stats.dropped_newest.fetch_add(1, Ordering::Relaxed);
+                Ok(false)
+            }
+            BackpressurePolicy::DropOldest => self.evict_and_send(command).await.map(|_| true),
+            BackpressurePolicy::SpillToDisk { .. } => {
+                let (Some(spill), Command::Log(entry)) = (&self.spill, &command) else {
+                    return Ok(false);
+                };
+                spill.push(entry)?;
+                self.stats.spilled.fetch_add(1, Ordering::Relaxed);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn evict_and_send(&self, mut command: Command) -> Result<()> {
+        let Some(evict) = &self.evict else {
+            return Err(LoggerError::ChannelClosed);
+        };
+
+        loop {
+            match self.sender.try_send(command) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(_)) => return Err(LoggerError::ChannelClosed),
+                Err(TrySendError::Full(returned)) => command = returned,
+            }
+
+            match evict.try_recv() {
+                Ok(Command::Log(_)) => {
+                    self.stats.dropped_oldest.fetch_add(1, Ordering::Relaxed);
+                    self.stats.messages_dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                // Control commands are never evicted; put them back behind us
+                Ok(control) => self
+                    .sender
+                    .send_async(control)
+                    .await
+                    .map_err(|_| LoggerError::ChannelClosed)?,
+                Err(_) => {}
+            }
+        }
+    }
+
+    async fn flush(&self) -> Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(LoggerError::ChannelClosed);
+        }
+
+        let (ack, done) = flume::bounded(1);
+        self.sender
+            .send_async(Command::Flush(ack))
+            .await
+            .map_err(|_| LoggerError::ChannelClosed)?;
+        // The ack sender is dropped unanswered if the processor stops first
+        done.recv_async()
+            .await
+            .map_err(|_| LoggerError::ChannelClosed)?
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        let worker = self.worker.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let Some(worker) = worker else {
+            return Ok(());
+        };
+        self.closed.store(true, Ordering::Release);
+
+        // The processor also stops when every sender is gone, so a failed
+        // send only means it is already on its way out.
+        let _ = self.sender.send_async(Command::Shutdown).await;
+        let result = match worker {
+            Worker::Task(handle) => handle
+                .await
+                .map_err(|e| LoggerError::Transport(format!("background processor failed: {}", e))),
+            // The sender is dropped without signalling if the thread panicked
+            Worker::Thread(done) => done.recv_async().await.map_err(|_| {
+                LoggerError::Transport("background processor thread panicked".to_string())
+            }),
+        };
+
+        // Our evict receiver keeps the channel open, so anything that raced
+        // in behind the shutdown command is still queued and will never be sent
+        if let Some(evict) = &self.evict {
+            let stranded = evict.drain().count() as u64;
+            self.stats
+                .messages_dropped
+                .fetch_add(stranded, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// Drive a future to completion on the current thread
+///
+/// Lets synchronous callers reuse the async queueing path. The futures it runs
+/// only wait on the channel, which wakes them from whichever thread frees up
+/// space, so parking here is enough.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// Builder for [`UltraLogger`]
+#[derive(Default)]
+pub struct UltraLoggerBuilder {
+    settings: LoggerSettings,
+    transport: BuilderTransport,
+}
+
+/// Everything the builder collects apart from the transport
+struct LoggerSettings {
+    service: String,
+    level: LogLevel,
+    service_levels: BTreeMap<String, LogLevel>,
+    buffer: BufferConfig,
+    sampling: SamplingConfig,
+    ttl: TtlConfig,
+    bandwidth: Option<BandwidthLimit>,
+    retry: Option<RetryConfig>,
+    compression: CompressionConfig,
+    format: SerializationFormat,
+    stats_interval: Option<Duration>,
+    clock_guard: Option<ClockGuardConfig>,
+    clock: Option<Arc<dyn Clock>>,
+    context: BTreeMap<String, LogValue>,
+    events: Option<EventRegistry>,
+}
+
+impl Default for LoggerSettings {
+    fn default() -> Self {
+        Self {
+            service: "ultra-logger".to_string(),
+            level: LogLevel::Debug,
+            service_levels: BTreeMap::new(),
+            buffer: BufferConfig::default(),
+            sampling: SamplingConfig::default(),
+            ttl: TtlConfig::default(),
+            bandwidth: None,
+            retry: None,
+            compression: CompressionConfig::default(),
+            format: SerializationFormat::default(),
+            stats_interval: None,
+            clock_guard: None,
+            clock: None,
+            context: BTreeMap::new(),
+            events: None,
+        }
+    }
+}
+
+enum BuilderTransport {
+    Kind(TransportKind),
+    Custom(Box<dyn Transport>),
+}
+
+impl Default for BuilderTransport {
+    fn default() -> Self {
+        BuilderTransport::Kind(TransportKind::default())
+    }
+}
+
+impl UltraLoggerBuilder {
+    /// Service name stamped on every entry
+    pub fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.settings.service = service.into();
+        self
+    }
+
+    /// Minimum level; anything below is discarded at the call site
+    pub fn with_level(mut self, level: LogLevel) -> Self {
+        self.settings.level = level;
+        self
+    }
+
+    /// Minimum level for one service, overriding the default
+    pub fn with_service_level(mut self, service: impl Into<String>, level: LogLevel) -> Self {
+        self.settings.service_levels.insert(service.into(), level);
+        self
+    }
+
+    /// Default and per-service levels from parsed directives
+    ///
+    /// Per-service entries are added to any set earlier; a default in the
+    /// spec replaces [`with_level`](UltraLoggerBuilder::with_level).
+    pub fn with_level_spec(mut self, spec: LevelSpec) -> Self {
+        if let Some(level) = spec.default {
+            self.settings.level = level;
+        }
+        self.settings.service_levels.extend(spec.services);
+        self
+    }
+
+    /// Built-in output transport (stdout by default)
+    pub fn with_transport(mut self, transport: TransportKind) -> Self {
+        self.transport = BuilderTransport::Kind(transport);
+        self
+    }
+
+    /// Custom output transport
+    pub fn with_custom_transport(mut self, transport: Box<dyn Transport>) -> Self {
+        self.transport = BuilderTransport::Custom(transport);
+        self
+    }
+
+    /// Level and transport from a [`LoggerConfig`]
+    ///
+    /// `level` is parsed as a [`LevelSpec`]. `transport_type` may be
+    /// `stdout`, `file` or `elasticsearch`. The file path comes from the
+    /// `path` connection option, falling back to `connection.host`.
+    /// Elasticsearch reads `host`, `port` and credentials from the connection
+    /// and `index_pattern`, `bulk_size`, `dead_letter_path` and
+    /// `allow_insecure_auth` from its options.
+    pub fn with_config(self, config: &LoggerConfig) -> Result<Self> {
+        let transport = match config.transport.transport_type.as_str() {
+            "stdout" => TransportKind::Stdout,
+            "file" => TransportKind::File(FileTransportConfig::from_connection(
+                &config.transport.connection,
+            )?),
+            "elasticsearch" => {
+                let connection = &config.transport.connection;
+                let defaults = ElasticsearchConfig::default();
+                let endpoint = if connection.host.starts_with("http://") {
+                    connection.host.clone()
+                } else {
+                    format!("http://{}:{}", connection.host, connection.port)
+                };
+                let option = |key: &str| connection.options.get(key);
+                TransportKind::Elasticsearch(ElasticsearchConfig {
+                    endpoint,
+                    index_pattern: option("index_pattern")
+                        .cloned()
+                        .unwrap_or(defaults.index_pattern),
+                    username: connection.username.clone(),
+                    password: connection.password.clone(),
+                    allow_insecure_auth: match option("allow_insecure_auth") {
+                        Some(allow) => allow.parse().map_err(|_| {
+                            LoggerError::Transport(format!(
+                                "invalid allow_insecure_auth: {}",
+                                allow
+                            ))
+                        })?,
+                        None => defaults.allow_insecure_auth,
+                    },
+                    bulk_size: match option("bulk_size") {
+                        Some(size) => size.parse().map_err(|_| {
+                            LoggerError::Transport(format!("invalid bulk_size: {}", size))
+                        })?,
+                        None => defaults.bulk_size,
+                    },
+                    dead_letter_path: option("dead_letter_path").map(std::path::PathBuf::from),
+                    ..defaults
+                })
+            }
+            other => {
+                return Err(LoggerError::Transport(format!(
+                    "unsupported transport type: {}",
+                    other
+                )))
+            }
+        };
+        Ok(self
+            .with_level_spec(LevelSpec::parse(&config.level)?)
+            .with_transport(transport))
+    }
+
+    /// Queue capacity and backpressure policy
+    pub fn with_buffer_config(mut self, buffer: BufferConfig) -> Self {
+        self.settings.buffer = buffer;
+        self
+    }
+
+    /// Per-level sampling rates
+    pub fn with_sampling(mut self, sampling: SamplingConfig) -> Self {
+        self.settings.sampling = sampling;
+        self
+    }
+
+    /// Per-level TTLs for entries waiting to be written
+    pub fn with_ttl(mut self, ttl: TtlConfig) -> Self {
+        self.settings.ttl = ttl;
+        self
+    }
+
+    /// Cap the bytes per second written to the transport
+    ///
+    /// Batches wait in the background processor rather than being dropped,
+    /// so a sustained overrun backs up into the queue and its policy.
+    pub fn with_bandwidth_limit(mut self, limit: BandwidthLimit) -> Self {
+        self.settings.bandwidth = Some(limit);
+        self
+    }
+
+    /// Resend batches the transport fails to accept instead of dropping them
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.settings.retry = Some(retry);
+        self
+    }
+
+    /// Write an `engine.stats` entry every `interval` with the pipeline's
+    /// throughput, drops, queue depth and delivery lag since the last one
+    ///
+    /// The processor adds the entry straight to its batch, so it lands in the
+    /// same store as the application's logs without taking queue space, and
+    /// regardless of the level filter and sampling.
+    pub fn with_stats_interval(mut self, interval: Duration) -> Self {
+        self.settings.stats_interval = Some(interval);
+        self
+    }
+
+    /// Detect wall-clock jumps, annotate the entries they affect and, if
+    /// configured, slew them out (see [`clock`])
+    pub fn with_clock_guard(mut self, guard: ClockGuardConfig) -> Self {
+        self.settings.clock_guard = Some(guard);
+        self
+    }
+
+    /// Clock the clock guard reads, e.g. a [`SimulatedClock`] in tests;
+    /// [`SystemClock`] by default
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.settings.clock = Some(clock);
+        self
+    }
+
+    /// Entry encoding within batches (NDJSON by default)
+    pub fn with_serialization_format(mut self, format: SerializationFormat) -> Self {
+        self.settings.format = format;
+        self
+    }
+
+    /// Compress each serialized batch before it reaches the transport
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.settings.compression = compression;
+        self
+    }
+
+    /// Field stamped on every entry (host, region, build, ...)
+    pub fn with_context(mut self, key: impl Into<String>, value: impl Into<LogValue>) -> Self {
+        self.settings.context.insert(key.into(), value.into());
+        self
+    }
+
+    /// Restrict event IDs and error codes to a known set, and mute some of them
+    pub fn with_event_registry(mut self, registry: EventRegistry) -> Self {
+        self.settings.events = Some(registry);
+        self
+    }
+
+    /// Open the transport and start the background processor
+    ///
+    /// Outside a tokio runtime the processor gets a dedicated thread.
+    pub fn build(self) -> Result<UltraLogger> {
+        let transport = match self.transport {
+            BuilderTransport::Kind(kind) => kind.open()?,
+            BuilderTransport::Custom(transport) => transport,
+        };
+        UltraLogger::spawn(self.settings, transport)
+    }
+}
+
+/// Drains the queue into batches and writes them to the transport
+struct BackgroundProcessor {
+    transport: Box<dyn Transport>,
+    spill: Option<Arc<SpillFile>>,
+    bandwidth: Option<TokenBucket>,
+    /// Queue depth at which the backlog is written newest first
+    lifo_threshold: Option<usize>,
+    /// Per-level TTL, indexed by `LogLevel as usize`
+    ttl: [Option<chrono::Duration>; 4],
+    stats: Arc<StatsCounters>,
+    buffer: Vec<u8>,
+    /// Level and event time of each entry in `buffer`, for delivery lag
+    in_flight: Vec<(LogLevel, DateTime<Utc>)>,
+    retry: Option<RetryQueue>,
+    /// Sequence number of the next batch
+    next_seq: u64,
+    compressor: Option<Compressor>,
+    /// Scratch space swapped with `buffer` when a batch is compressed
+    compressed: Vec<u8>,
+    format: SerializationFormat,
+    stats_report: Option<StatsReport>,
+    clock: Option<Arc<ClockGuard>>,
+}
+
+/// Schedule and baseline for the periodic `engine.stats` entry
+struct StatsReport {
+    service: Arc<str>,
+    interval: Duration,
+    due: Instant,
+    /// Counters and time as of the previous report
+    last: LoggerStats,
+    last_at: Instant,
+}
+
+impl StatsReport {
+    fn new(service: &str, interval: Duration) -> Self {
+        let interval = interval.max(Duration::from_millis(1));
+        let now = Instant::now();
+        Self {
+            service: service.into(),
+            interval,
+            due: now + interval,
+            last: LoggerStats::default(),
+            last_at: now,
+        }
+    }
+
+    /// Entry describing the interval since the previous report
+    fn entry(&mut self, time: DateTime<Utc>, stats: LoggerStats, queue_depth: usize) -> LogEntry {
+        let now = Instant::now();
+        let elapsed = now - self.last_at;
+        let last = std::mem::replace(&mut self.last, stats);
+        self.last_at = now;
+        self.due = now + self.interval;
+
+        let mut lag = LagHistogram::default();
+        for (current, previous) in stats.delivery_lag.iter().zip(&last.delivery_lag) {
+            for (bucket, (a, b)) in current.counts.iter().zip(&previous.counts).enumerate() {
+                lag.counts[bucket] += a - b;
+            }
+            lag.max_ms = lag.max_ms.max(current.max_ms);
+        }
+        let lag_ms = |q| {
+            lag.quantile(q)
+                .map_or(0, |lag| u64::try_from(lag.as_millis()).unwrap_or(u64::MAX))
+        };
+        let logged = stats.messages_logged - last.messages_logged;
+
+        let fields = [
+            ("interval_ms", LogValue::from(elapsed.as_millis() as u64)),
+            ("messages_logged", LogValue::from(logged)),
+            (
+                "throughput_per_sec",
+                LogValue::F64(logged as f64 / elapsed.as_secs_f64().max(f64::EPSILON)),
+            ),
+            (
+                "messages_dropped",
+                LogValue::from(stats.messages_dropped - last.messages_dropped),
+            ),
+            (
+                "bytes_written",
+                LogValue::from(stats.bytes_written - last.bytes_written),
+            ),
+            (
+                "transport_errors",
+                LogValue::from(stats.transport_errors - last.transport_errors),
+            ),
+            ("queue_depth", LogValue::from(queue_depth as u64)),
+            ("delivered", LogValue::from(lag.count())),
+            ("lag_p50_ms", LogValue::from(lag_ms(0.5))),
+            ("lag_p99_ms", LogValue::from(lag_ms(0.99))),
+        ];
+        LogEntry {
+            timestamp: time,
+            level: LogLevel::Info,
+            service: Arc::clone(&self.service),
+            message: CompactString::const_new("engine.stats"),
+            event_time: None,
+            event_id: None,
+            error_code: None,
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        }
+    }
+}
+
+impl BackgroundProcessor {
+    async fn run(mut self, receiver: flume::Receiver<Command>) {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut deadline = Instant::now();
+
+        loop {
+            if batch.is_empty() && receiver.is_empty() {
+                self.replay_spilled().await;
+            }
+
+            let now = Instant::now();
+            let stats_due = self.stats_report.as_ref().map(|report| report.due);
+            if stats_due.is_some_and(|due| due <= now) {
+                if batch.is_empty() {
+                    deadline = now + BATCH_TIMEOUT;
+                }
+                let (time, stats) = (self.now(), self.stats.snapshot());
+                if let Some(report) = self.stats_report.as_mut() {
+                    batch.push(report.entry(time, stats, receiver.len()));
+                }
+                continue;
+            }
+
+            let command = if batch.is_empty() {
+                let retry_due = self.retry.as_ref().and_then(RetryQueue::next_due);
+                match retry_due.into_iter().chain(stats_due).min() {
+                    Some(due) => match tokio::time::timeout_at(due, receiver.recv_async()).await {
+                        Ok(received) => received.ok(),
+                        Err(_) => {
+                            self.retry_failed(false).await;
+                            continue;
+                        }
+                    },
+                    None => receiver.recv_async().await.ok(),
+                }
+            } else {
+                let wake = stats_due.map_or(deadline, |due| due.min(deadline));
+                match tokio::time::timeout_at(wake, receiver.recv_async()).await {
+                    Ok(received) => received.ok(),
+                    Err(_) => {
+                        if Instant::now() >= deadline {
+                            self.flush_batch(&mut batch).await;
+                        }
+                        continue;
+                    }
+                }
+            };
+
+            let control = match command {
+                Some(Command::Log(entry)) => {
+                    if batch.is_empty() {
+                        deadline = Instant::now() + BATCH_TIMEOUT;
+                    }
+                    batch.push(entry);
+                    if self.backlogged(&receiver) {
+                        match self.drain_newest_first(&receiver, &mut batch).await {
+                            Some(control) => control,
+                            None => continue,
+                        }
+                    } else {
+                        if batch.len() >= BATCH_SIZE {
+                            self.flush_batch(&mut batch).await;
+                        }
+                        continue;
+                    }
+                }
+                Some(control) => control,
+                None => Command::Shutdown,
+            };
+
+            if !self.control(control, &mut batch).await {
+                return;
+            }
+        }
+    }
+
+    /// Handle a flush or shutdown; returns whether to keep running
+    async fn control(&mut self, command: Command, batch: &mut Vec<LogEntry>) -> bool {
+        self.flush_batch(batch).await;
+        self.replay_spilled().await;
+
+        match command {
+            Command::Log(_) => true,
+            Command::Flush(ack) => {
+                let flushed = self.transport.flush().await;
+                if flushed.is_err() {
+                    self.stats.transport_errors.fetch_add(1, Ordering::Relaxed);
+                }
+                let _ = ack.send(flushed);
+                true
+            }
+            Command::Shutdown => {
+                self.retry_failed(true).await;
+                if let Some(retry) = &mut self.retry {
+                    for batch in retry.take_due(true) {
+                        self.dead_letter(batch, "logger shut down");
+                    }
+                }
+                if self.transport.shutdown().await.is_err() {
+                    self.stats.transport_errors.fetch_add(1, Ordering::Relaxed);
+                }
+                false
+            }
+        }
+    }
+
+    fn backlogged(&self, receiver: &flume::Receiver<Command>) -> bool {
+        self.lifo_threshold
+            .is_some_and(|threshold| receiver.len() >= threshold)
+    }
+
+    /// Write the current batch and everything queued behind it newest first
+    ///
+    /// Stops at the first control command so flush and shutdown still cover
+    /// exactly the entries queued before them; that command is returned.
+    async fn drain_newest_first(
+        &mut self,
+        receiver: &flume::Receiver<Command>,
+        batch: &mut Vec<LogEntry>,
+    ) -> Option<Command> {
+        // Bounded by the queue capacity so producers can't keep us here
+        let limit = batch.len() + receiver.capacity().unwrap_or(usize::MAX);
+        let mut backlog = std::mem::take(batch);
+        let mut control = None;
+        while backlog.len() < limit {
+            match receiver.try_recv() {
+                Ok(Command::Log(entry)) => backlog.push(entry),
+                Ok(command) => {
+                    control = Some(command);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+
+        self.stats.lifo_drains.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .reordered
+            .fetch_add(backlog.len() as u64, Ordering::Relaxed);
+
+        while !backlog.is_empty() {
+            let start = backlog.len().saturating_sub(BATCH_SIZE);
+            let mut newest: Vec<LogEntry> = backlog.drain(start..).rev().collect();
+            self.flush_batch(&mut newest).await;
+        }
+        control
+    }
+
+    /// Deliver entries that overflowed to the spill file, a batch at a time
+    ///
+    /// Covers what was spilled when called, so producers that keep spilling
+    /// can't hold the processor here.
+    async fn replay_spilled(&mut self) {
+        let Some(spill) = self.spill.clone() else {
+            return;
+        };
+
+        let mut remaining = spill.pending();
+        while remaining > 0 && spill.has_pending() {
+            let mut chunk = match spill.take(BATCH_SIZE) {
+                Ok(chunk) => chunk,
+                Err(_) => {
+                    self.stats.transport_errors.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            };
+            let lines = chunk.entries.len() as u64 + chunk.skipped;
+            if lines == 0 {
+                return;
+            }
+            remaining = remaining.saturating_sub(lines);
+            self.stats
+                .spill_recovered
+                .fetch_add(chunk.entries.len() as u64, Ordering::Relaxed);
+            self.stats
+                .messages_dropped
+                .fetch_add(chunk.skipped, Ordering::Relaxed);
+            self.flush_batch(&mut chunk.entries).await;
+        }
+    }
+
+    /// Current time on the same clock as entry timestamps
+    fn now(&self) -> DateTime<Utc> {
+        match &self.clock {
+            Some(clock) => clock.now(),
+            None => Utc::now(),
+        }
+    }
+
+    /// Remove entries that outlived their level's TTL while queued
+    fn drop_expired(&self, batch: &mut Vec<LogEntry>) {
+        if batch.is_empty() || self.ttl.iter().all(Option::is_none) {
+            return;
+        }
+
+        let now = self.now();
+        let before = batch.len();
+        batch.retain(|entry| match self.ttl[entry.level as usize] {
+            Some(ttl) => now - entry.timestamp <= ttl,
+            None => true,
+        });
+
+        let expired = (before - batch.len()) as u64;
+        if expired > 0 {
+            self.stats.expired.fetch_add(expired, Ordering::Relaxed);
+            self.stats
+                .messages_dropped
+                .fetch_add(expired, Ordering::Relaxed);
+        }
+    }
+
+    /// Serialize the batch as NDJSON and hand it to the transport
+    async fn flush_batch(&mut self, batch: &mut Vec<LogEntry>) {
+        self.retry_failed(false).await;
+        self.drop_expired(batch);
+        if batch.is_empty() {
+            return;
+        }
+
+        let started = Instant::now();
+        self.buffer.clear();
+        self.in_flight.clear();
+        let mut serialized = 0u64;
+        for entry in batch.iter() {
+            let start = self.buffer.len();
+            if format::write_entry(self.format, &mut self.buffer, entry).is_ok() {
+                self.in_flight
+                    .push((entry.level, entry.time(TimestampSource::Event)));
+                serialized += 1;
+            } else {
+                self.buffer.truncate(start);
+                self.stats.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        batch.clear();
+        self.stats.serialization.record(started.elapsed());
+        self.compress_buffer();
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        // Taken so `send` can borrow the processor; a parked batch keeps it
+        let buffer = std::mem::take(&mut self.buffer);
+        match self.send(&buffer).await {
+            Ok(()) => {
+                self.delivered(buffer.len(), &self.in_flight);
+                self.buffer = buffer;
+            }
+            Err(e) => match &mut self.retry {
+                Some(retry) => {
+                    let entries = std::mem::take(&mut self.in_flight);
+                    if let Some(given_up) = retry.push(seq, buffer, entries, e.to_string()) {
+                        // This batch when retries are off, else the oldest
+                        // one pushed out to make room
+                        let reason = if given_up.seq == seq {
+                            "retries disabled"
+                        } else {
+                            "retry queue full"
+                        };
+                        self.dead_letter(given_up, reason);
+                    }
+                }
+                None => {
+                    self.stats
+                        .messages_dropped
+                        .fetch_add(serialized, Ordering::Relaxed);
+                    self.buffer = buffer;
+                }
+            },
+        }
+    }
+
+    /// Replace the serialized batch in `buffer` with its compressed form
+    fn compress_buffer(&mut self) {
+        let Some(compressor) = &self.compressor else {
+            return;
+        };
+        let started = Instant::now();
+        // Below the threshold, or failed: send it uncompressed
+        if let Ok(true) = compressor.compress(&self.buffer, &mut self.compressed) {
+            self.stats.compression.record(started.elapsed());
+            self.stats
+                .compression_input_bytes
+                .fetch_add(self.buffer.len() as u64, Ordering::Relaxed);
+            self.stats
+                .compression_output_bytes
+                .fetch_add(self.compressed.len() as u64, Ordering::Relaxed);
+            std::mem::swap(&mut self.buffer, &mut self.compressed);
+        }
+    }
+
+    /// Hand bytes to the transport, within the bandwidth limit
+    async fn send(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(bucket) = &mut self.bandwidth {
+            let wait = bucket.acquire(bytes.len());
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+                self.stats.throttle.record(wait);
+            }
+        }
+
+        let started = Instant::now();
+        let written = self.transport.send_batch(bytes).await;
+        self.stats.transport.record(started.elapsed());
+        if written.is_err() {
+            self.stats.transport_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        written
+    }
+
+    /// Account for a batch the transport accepted
+    fn delivered(&self, bytes: usize, entries: &[(LogLevel, DateTime<Utc>)]) {
+        self.stats.batches_flushed.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+
+        let acked = self.now();
+        for (level, time) in entries {
+            let lag_ms = (acked - *time).num_milliseconds().max(0) as u64;
+            self.stats.delivery_lag[*level as usize].record(lag_ms);
+        }
+    }
+
+    /// Resend parked batches that are due, or all of them
+    async fn retry_failed(&mut self, all: bool) {
+        let Some(retry) = &mut self.retry else {
+            return;
+        };
+        for batch in retry.take_due(all) {
+            self.stats.retried.fetch_add(1, Ordering::Relaxed);
+            match self.send(&batch.bytes).await {
+                Ok(()) => self.delivered(batch.bytes.len(), &batch.entries),
+                Err(e) => {
+                    let exhausted = self
+                        .retry
+                        .as_mut()
+                        .and_then(|retry| retry.reschedule(batch, e.to_string()));
+                    if let Some(batch) = exhausted {
+                        self.dead_letter(batch, "retries exhausted");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Give up on a batch: dead-letter it if configured, with `reason` and
+    /// its last transport error, else count it dropped
+    fn dead_letter(&self, batch: FailedBatch, reason: &str) {
+        let error = format!("{}: {}", reason, batch.error);
+        let written = self
+            .retry
+            .as_ref()
+            .map(|retry| retry.dead_letter(&batch, &error));
+        if let Some(Ok(true)) = written {
+            self.stats.dead_lettered.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.stats
+                .messages_dropped
+                .fetch_add(batch.entries.len() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Bytes a test transport has been handed
+    pub(crate) type Output = Arc<Mutex<Vec<u8>>>;
+
+    /// Transport that holds every batch until let through, so the queue can
+    /// be filled behind it
+    pub(crate) struct Gated {
+        entered: flume::Sender<()>,
+        gate: flume::Receiver<()>,
+        output: Output,
+    }
+
+    impl Gated {
+        /// The transport, a receiver signalled as each send starts, the
+        /// gate (one message lets one send through; dropping it opens it for
+        /// good) and the bytes sent so far
+        pub(crate) fn new() -> (Self, flume::Receiver<()>, flume::Sender<()>, Output) {
+            let (entered, entered_rx) = flume::unbounded();
+            let (gate_tx, gate) = flume::unbounded();
+            let output = Output::default();
+            let transport = Self {
+                entered,
+                gate,
+                output: Arc::clone(&output),
+            };
+            (transport, entered_rx, gate_tx, output)
+        }
+    }
+
+    #[async_trait]
+    impl Transport for Gated {
+        async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+            let _ = self.entered.send(());
+            // Open for good once the gate's sender is dropped
+            let _ = self.gate.recv_async().await;
+            self.output.lock().unwrap().extend_from_slice(batch);
+            Ok(())
+        }
+    }
+
+    struct Stalled {
+        logger: UltraLogger,
+        entered: flume::Receiver<()>,
+        gate: Option<flume::Sender<()>>,
+        output: Output,
+    }
+
+    impl Stalled {
+        /// Logger whose processor is stuck sending a first batch, with an
+        /// empty queue of `capacity` in front of it
+        async fn new(capacity: usize, backpressure: BackpressurePolicy) -> Self {
+            Self::with(UltraLogger::builder().with_buffer_config(BufferConfig {
+                capacity,
+                backpressure,
+                ..Default::default()
+            }))
+            .await
+        }
+
+        /// Logger from `builder` whose processor is stuck sending a first
+        /// batch, the single entry "stall"
+        async fn with(builder: UltraLoggerBuilder) -> Self {
+            let (transport, entered, gate, output) = Gated::new();
+            let logger = builder
+                .with_custom_transport(Box::new(transport))
+                .build()
+                .unwrap();
+            logger.info("stall".into()).await.unwrap();
+            entered.recv_async().await.unwrap();
+            Self {
+                logger,
+                entered,
+                gate: Some(gate),
+                output,
+            }
+        }
+
+        /// Let the batch being sent through, then wait for the processor
+        /// to get stuck on the next one
+        async fn step(&self) {
+            self.gate.as_ref().unwrap().send(()).unwrap();
+            self.entered.recv_async().await.unwrap();
+        }
+
+        fn open(&mut self) {
+            self.gate = None;
+        }
+
+        fn written(&self) -> Vec<String> {
+            let output = self.output.lock().unwrap();
+            serde_json::Deserializer::from_slice(&output)
+                .into_iter::<LogEntry>()
+                .map(|entry| entry.unwrap().message.to_string())
+                .collect()
+        }
+    }
+
+    async fn log_all(logger: &UltraLogger, messages: &[&str]) {
+        for message in messages {
+            logger.info(message.to_string()).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn stage_timing_buckets_and_quantiles() {
+        let counters = StageCounters::default();
+        for micros in [1, 3, 3, 40, 40, 40, 40, 40, 900, 250_000] {
+            counters.record(Duration::from_micros(micros));
+        }
+        let timing = counters.snapshot();
+        assert_eq!(timing.samples, 10);
+        assert_eq!(timing.counts.iter().sum::<u64>(), 10);
+        assert_eq!(timing.counts[0], 1);
+        assert_eq!(timing.counts[2], 2);
+        assert_eq!(timing.counts[5], 5);
+        assert_eq!(timing.counts[STAGE_BUCKET_BOUNDS_US.len()], 1);
+
+        assert_eq!(timing.quantile(0.5), Some(Duration::from_micros(50)));
+        assert_eq!(timing.quantile(0.9), Some(Duration::from_micros(1_000)));
+        // The overflow bucket reports the slowest measurement
+        assert_eq!(timing.quantile(1.0), Some(Duration::from_micros(250_000)));
+        assert_eq!(StageTiming::default().quantile(0.5), None);
+
+        // A bound never overstates the slowest measurement
+        let counters = StageCounters::default();
+        counters.record(Duration::from_nanos(1_500));
+        assert_eq!(
+            counters.snapshot().quantile(0.5),
+            Some(Duration::from_nanos(1_500))
+        );
+    }
+
+    #[test]
+    fn call_site_stages_are_timed_on_sampled_calls() {
+        let logger = UltraLogger::builder()
+            .with_level(LogLevel::Info)
+            .with_custom_transport(Box::new(Discard))
+            .build()
+            .unwrap();
+        for _ in 0..STAGE_TIMING_SAMPLE * 4 {
+            logger
+                .log_blocking(LogLevel::Info, "kept".into(), &[])
+                .unwrap();
+        }
+        let stats = logger.stats();
+        assert_eq!(stats.filter.samples, 4);
+        assert_eq!(stats.enrichment.samples, 4);
+
+        // Filtered-out calls are timed through the filter only
+        for _ in 0..STAGE_TIMING_SAMPLE * 4 {
+            logger
+                .log_blocking(LogLevel::Debug, "dropped".into(), &[])
+                .unwrap();
+        }
+        let stats = logger.stats();
+        assert_eq!(stats.filter.samples, 8);
+        assert_eq!(stats.filter.counts.iter().sum::<u64>(), 8);
+        assert_eq!(stats.enrichment.samples, 4);
+    }
+
+    pub(crate) struct Discard;
+
+    #[async_trait]
+    impl Transport for Discard {
+        async fn send_batch(&mut self, _batch: &[u8]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn block_waits_for_room() {
+        let mut stalled = Stalled::new(2, BackpressurePolicy::Block).await;
+        log_all(&stalled.logger, &["a", "b"]).await;
+
+        let logger = stalled.logger.clone();
+        let blocked = tokio::spawn(async move { logger.info("c".into()).await });
+        tokio::task::yield_now().await;
+        assert!(!blocked.is_finished());
+        assert_eq!(stalled.logger.stats().queue_full_waits, 1);
+
+        stalled.open();
+        blocked.await.unwrap().unwrap();
+        stalled.logger.shutdown().await.unwrap();
+
+        assert_eq!(stalled.written(), ["stall", "a", "b", "c"]);
+        let stats = stalled.logger.stats();
+        assert_eq!(stats.messages_logged, 4);
+        assert_eq!(stats.messages_dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn drop_newest_discards_the_entry_being_logged() {
+        let mut stalled = Stalled::new(2, BackpressurePolicy::DropNewest).await;
+        log_all(&stalled.logger, &["a", "b", "c", "d"]).await;
+
+        stalled.open();
+        stalled.logger.shutdown().await.unwrap();
+
+        assert_eq!(stalled.written(), ["stall", "a", "b"]);
+        let stats = stalled.logger.stats();
+        assert_eq!(stats.dropped_newest, 2);
+        assert_eq!(stats.messages_dropped, 2);
+        assert_eq!(stats.messages_logged, 3);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_evicts_entries_but_not_flush() {
+        let mut stalled = Stalled::new(2, BackpressurePolicy::DropOldest).await;
+        log_all(&stalled.logger, &["a"]).await;
+
+        // Queue: a, Flush
+        let logger = stalled.logger.clone();
+        let flushed = tokio::spawn(async move { logger.flush().await });
+        tokio::task::yield_now().await;
+
+        // Each needs room: the first evicts a, the second passes over the
+        // flush (requeued behind) and evicts c
+        log_all(&stalled.logger, &["c", "d"]).await;
+
+        stalled.open();
+        flushed.await.unwrap().unwrap();
+        stalled.logger.shutdown().await.unwrap();
+
+        assert_eq!(stalled.written(), ["stall", "d"]);
+        let stats = stalled.logger.stats();
+        assert_eq!(stats.dropped_oldest, 2);
+        assert_eq!(stats.messages_dropped, 2);
+    }
+
+    #[tokio::test]
+    async fn spill_to_disk_replays_overflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spill.ndjson");
+        let mut stalled =
+            Stalled::new(2, BackpressurePolicy::SpillToDisk { path: path.clone() }).await;
+        log_all(&stalled.logger, &["a", "b", "c", "d", "e"]).await;
+        assert_eq!(stalled.logger.stats().spilled, 3);
+
+        stalled.open();
+        stalled.logger.flush().await.unwrap();
+
+        // Spilled entries follow the ones that were queued
+        assert_eq!(stalled.written(), ["stall", "a", "b", "c", "d", "e"]);
+        let stats = stalled.logger.stats();
+        assert_eq!(stats.spill_recovered, 3);
+        assert_eq!(stats.messages_logged, 6);
+        assert_eq!(stats.messages_dropped, 0);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        stalled.logger.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn spill_left_by_a_previous_run_is_replayed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spill.ndjson");
+        let line = |message: &str| {
+            let entry = LogEntry {
+                timestamp: Utc::now(),
+                level: LogLevel::Warn,
+                service: "previous".into(),
+                message: message.into(),
+                event_time: None,
+                event_id: None,
+                error_code: None,
+                fields: BTreeMap::new(),
+            };
+            let mut line = serde_json::to_vec(&entry).unwrap();
+            line.push(b'\n');
+            line
+        };
+        let torn = line("torn");
+        let contents = [
+            line("kept"),
+            b"not json\n".to_vec(),
+            line("also kept"),
+            torn[..torn.len() / 2].to_vec(),
+        ];
+        std::fs::write(&path, contents.concat()).unwrap();
+
+        let mut stalled = Stalled::new(8, BackpressurePolicy::SpillToDisk { path }).await;
+        stalled.open();
+        stalled.logger.shutdown().await.unwrap();
+
+        let written = stalled.written();
+        let replayed: Vec<&str> = written
+            .iter()
+            .map(String::as_str)
+            .filter(|message| !["stall", "spill file recovered"].contains(message))
+            .collect();
+        assert_eq!(replayed, ["kept", "also kept"]);
+        assert!(written
+            .iter()
+            .any(|message| message == "spill file recovered"));
+        assert_eq!(stalled.logger.stats().spill_recovered, 2);
+        assert_eq!(stalled.logger.stats().messages_dropped, 1);
+    }
+
+    struct Refused;
+
+    #[async_trait]
+    impl Transport for Refused {
+        async fn send_batch(&mut self, _batch: &[u8]) -> Result<()> {
+            Err(LoggerError::Transport("connection refused".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_batch_without_retries_is_dead_lettered_with_its_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead.letter");
+        let logger = UltraLogger::builder()
+            .with_retry(RetryConfig {
+                max_retries: 0,
+                dead_letter_path: Some(path.clone()),
+                ..Default::default()
+            })
+            .with_custom_transport(Box::new(Refused))
+            .build()
+            .unwrap();
+        logger.info("lost".into()).await.unwrap();
+        logger.shutdown().await.unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        let newline = contents.iter().position(|&b| b == b'\n').unwrap();
+        let header: serde_json::Value = serde_json::from_slice(&contents[..newline]).unwrap();
+        let error = header["error"].as_str().unwrap();
+        assert!(error.starts_with("retries disabled: "), "{error}");
+        assert!(error.contains("connection refused"), "{error}");
+        assert_eq!(header["attempts"], 1);
+        assert_eq!(header["entries"], 1);
+
+        let body = &contents[newline + 1..];
+        assert_eq!(body.len() as u64, header["bytes"].as_u64().unwrap());
+        let entry: LogEntry = serde_json::from_slice(body).unwrap();
+        assert_eq!(entry.message, "lost");
+
+        let stats = logger.stats();
+        assert_eq!(stats.dead_lettered, 1);
+        assert_eq!(stats.messages_dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn nonblocking_log_inside_a_runtime_drops_instead_of_waiting() {
+        let mut stalled = Stalled::new(1, BackpressurePolicy::Block).await;
+        log_all(&stalled.logger, &["a"]).await;
+
+        // On this current-thread runtime, waiting would never return
+        stalled
+            .logger
+            .log_nonblocking(LogLevel::Info, "b".into())
+            .unwrap();
+        let stats = stalled.logger.stats();
+        assert_eq!(stats.dropped_newest, 1);
+        assert_eq!(stats.queue_full_waits, 0);
+
+        stalled.open();
+        stalled.logger.shutdown().await.unwrap();
+        assert_eq!(stalled.written(), ["stall", "a"]);
+    }
+
+    #[tokio::test]
+    async fn flush_returns_once_earlier_entries_are_written() {
+        let mut stalled = Stalled::new(16, BackpressurePolicy::Block).await;
+        log_all(&stalled.logger, &["a", "b", "c"]).await;
+
+        let logger = stalled.logger.clone();
+        let flushed = tokio::spawn(async move { logger.flush().await });
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!flushed.is_finished());
+
+        stalled.open();
+        flushed.await.unwrap().unwrap();
+        // Checked before shutdown, which would write them anyway
+        assert_eq!(stalled.written(), ["stall", "a", "b", "c"]);
+        stalled.logger.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lifo_drain_only_over_the_threshold() {
+        let mut stalled = Stalled::with(UltraLogger::builder().with_buffer_config(BufferConfig {
+            capacity: 16,
+            drain_order: DrainOrder::LifoOverThreshold { threshold: 3 },
+            ..Default::default()
+        }))
+        .await;
+
+        // Two queued: under the threshold, written in order (and then stuck)
+        log_all(&stalled.logger, &["a", "b"]).await;
+        stalled.step().await;
+        assert_eq!(stalled.logger.stats().lifo_drains, 0);
+
+        // Four queued: over it, written newest first
+        log_all(&stalled.logger, &["c", "d", "e", "f"]).await;
+        stalled.open();
+        stalled.logger.shutdown().await.unwrap();
+
+        assert_eq!(stalled.written(), ["stall", "a", "b", "f", "e", "d", "c"]);
+        let stats = stalled.logger.stats();
+        assert_eq!(stats.lifo_drains, 1);
+        assert_eq!(stats.reordered, 4);
+    }
+
+    /// Logger builder whose timestamps come from `clock`
+    fn clocked(clock: &Arc<SimulatedClock>) -> UltraLoggerBuilder {
+        UltraLogger::builder()
+            .with_level(LogLevel::Debug)
+            .with_clock(clock.clone())
+            .with_clock_guard(ClockGuardConfig::default())
+    }
+
+    #[tokio::test]
+    async fn queued_entries_expire_by_level() {
+        let clock = Arc::new(SimulatedClock::new(Utc::now()));
+        let mut stalled = Stalled::with(clocked(&clock).with_ttl(TtlConfig {
+            debug: Some(Duration::from_secs(1)),
+            info: Some(Duration::from_secs(5)),
+            ..Default::default()
+        }))
+        .await;
+        stalled.logger.debug("d1".into()).await.unwrap();
+        stalled.logger.error("e".into()).await.unwrap();
+        stalled.logger.debug("d2".into()).await.unwrap();
+        stalled.logger.info("i".into()).await.unwrap();
+
+        // Past the Debug TTL, within the Info one; Error has none
+        clock.advance(Duration::from_secs(2));
+        stalled.open();
+        stalled.logger.shutdown().await.unwrap();
+
+        assert_eq!(stalled.written(), ["stall", "e", "i"]);
+        let stats = stalled.logger.stats();
+        assert_eq!(stats.expired, 2);
+        assert_eq!(stats.messages_dropped, 2);
+        assert_eq!(stats.messages_logged, 5);
+    }
+
+    #[tokio::test]
+    async fn delivery_lag_is_recorded_per_level() {
+        let clock = Arc::new(SimulatedClock::new(Utc::now()));
+        let mut stalled = Stalled::with(clocked(&clock)).await;
+        stalled.logger.info("a".into()).await.unwrap();
+        stalled.logger.error("e".into()).await.unwrap();
+
+        clock.advance(Duration::from_millis(1_500));
+        stalled.open();
+        stalled.logger.shutdown().await.unwrap();
+
+        let lag = stalled.logger.stats().delivery_lag;
+        // 1.5s lands in the bucket up to 2s
+        let bucket = LAG_BUCKET_BOUNDS_MS
+            .iter()
+            .position(|&bound| bound == 2_000)
+            .unwrap();
+        let info = &lag[LogLevel::Info as usize];
+        assert_eq!(info.count(), 2);
+        assert_eq!(info.counts[bucket], 2);
+        assert_eq!(info.max_ms, 1_500);
+        assert_eq!(info.fraction_within(Duration::from_secs(1)), 0.0);
+        assert_eq!(info.fraction_within(Duration::from_secs(2)), 1.0);
+
+        let error = &lag[LogLevel::Error as usize];
+        assert_eq!(error.count(), 1);
+        assert_eq!(error.counts[bucket], 1);
+        assert_eq!(lag[LogLevel::Debug as usize].count(), 0);
+        assert_eq!(lag[LogLevel::Warn as usize].count(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stats_entry_is_written_every_interval() {
+        let mut stalled =
+            Stalled::with(UltraLogger::builder().with_stats_interval(Duration::from_secs(1))).await;
+        stalled.open();
+        log_all(&stalled.logger, &["a", "b", "c"]).await;
+        tokio::time::sleep(Duration::from_millis(2_500)).await;
+        stalled.logger.shutdown().await.unwrap();
+
+        let output = stalled.output.lock().unwrap().clone();
+        let reports: Vec<LogEntry> = serde_json::Deserializer::from_slice(&output)
+            .into_iter::<LogEntry>()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.message == "engine.stats")
+            .collect();
+        assert_eq!(reports.len(), 2);
+
+        // Counters read back from JSON as signed integers
+        let int = |report: &LogEntry, name: &str| match report.fields[name] {
+            LogValue::I64(value) => value,
+            ref other => panic!("{name} is {other:?}"),
+        };
+        let first = &reports[0];
+        assert_eq!(first.level, LogLevel::Info);
+        assert_eq!(int(first, "interval_ms"), 1_000);
+        assert_eq!(int(first, "messages_logged"), 4);
+        assert_eq!(first.fields["throughput_per_sec"], LogValue::F64(4.0));
+        assert_eq!(int(first, "messages_dropped"), 0);
+        assert_eq!(int(first, "transport_errors"), 0);
+        assert_eq!(int(first, "queue_depth"), 0);
+        assert_eq!(int(first, "delivered"), 4);
+        assert!(int(first, "bytes_written") > 0);
+        assert!(int(first, "lag_p99_ms") >= int(first, "lag_p50_ms"));
+
+        // The second covers only its own interval, in which the first
+        // report itself was the one entry delivered
+        assert_eq!(int(&reports[1], "messages_logged"), 0);
+        assert_eq!(int(&reports[1], "delivered"), 1);
+    }
+}
@@ -0,0 +1,92 @@
+//! Bridge from the `log` crate facade into an [`UltraLogger`]
+
+use crate::error::{LoggerError, Result};
+use crate::{block_on, LogLevel, LogValue, UltraLogger};
+use log::{Log, Metadata, Record};
+
+/// `log::Log` implementation that forwards records into an [`UltraLogger`]
+///
+/// Each record becomes an entry with the logger's service name, the record's
+/// message and `target`, `file` and `line` fields. `Trace` maps to
+/// [`LogLevel::Debug`], since the pipeline has no lower level.
+///
+/// `log` calls are synchronous: under `BackpressurePolicy::Block` a full
+/// queue blocks the calling thread, and [`Log::flush`] blocks until the
+/// background processor acknowledges. Neither should be called from the
+/// thread of a current-thread runtime that also hosts the processor.
+///
+/// ```
+/// use ultra_logger::{LogAdapter, Result, UltraLogger};
+///
+/// # fn main() -> Result<()> {
+/// let logger = UltraLogger::new("pricing".to_string());
+/// LogAdapter::init(logger.clone())?;
+///
+/// log::info!(target: "pricing::curve", "curve rebuilt");
+/// log::logger().flush();
+/// # Ok(())
+/// # }
+/// ```
+pub struct LogAdapter {
+    logger: UltraLogger,
+}
+
+impl LogAdapter {
+    pub fn new(logger: UltraLogger) -> Self {
+        Self { logger }
+    }
+
+    /// Install an adapter for `logger` as the process-wide `log` backend
+    ///
+    /// Fails with [`LoggerError::AlreadyInitialized`] if a `log` backend is
+    /// already installed.
+    pub fn init(logger: UltraLogger) -> Result<()> {
+        log::set_boxed_logger(Box::new(Self::new(logger)))
+            .map_err(|_| LoggerError::AlreadyInitialized)?;
+        log::set_max_level(log::LevelFilter::Trace);
+        Ok(())
+    }
+}
+
+fn map_level(level: log::Level) -> LogLevel {
+    match level {
+        log::Level::Error => LogLevel::Error,
+        log::Level::Warn => LogLevel::Warn,
+        log::Level::Info => LogLevel::Info,
+        log::Level::Debug | log::Level::Trace => LogLevel::Debug,
+    }
+}
+
+impl Log for LogAdapter {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.logger.enabled(map_level(metadata.level()))
+    }
+
+    fn log(&self, record: &Record) {
+        let level = map_level(record.level());
+        if !self.logger.enabled(level) {
+            return;
+        }
+
+        let message = match record.args().as_str() {
+            Some(message) => message.to_string(),
+            None => record.args().to_string(),
+        };
+
+        let mut fields = Vec::with_capacity(3);
+        fields.push(("target", LogValue::from(record.target())));
+        if let Some(file) = record.file() {
+            fields.push(("file", LogValue::from(file)));
+        }
+        if let Some(line) = record.line() {
+            fields.push(("line", LogValue::from(line)));
+        }
+
+        // `log` has no error channel; failures show up in the logger's stats
+        let _ = self.logger.log_blocking(level, message, &fields);
+    }
+
+    fn flush(&self) {
+        let _ = block_on(self.logger.flush());
+    }
+}
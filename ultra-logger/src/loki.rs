@@ -0,0 +1,177 @@
+//! Grafana Loki output through the JSON push API
+//!
+//! Each batch is re-read from NDJSON and split into streams by label set:
+//! `service`, `level`, and `module` when the entry has one (a `module`
+//! field, or the `target` the [`LogAdapter`](crate::LogAdapter) records).
+//! The log line is the entry's own JSON, so LogQL's `| json` recovers every
+//! field. Bodies are gzipped unless disabled.
+
+use crate::error::{LoggerError, Result};
+use crate::http::{Endpoint, RetryPolicy};
+use crate::transport::Transport;
+use crate::{LogEntry, LogLevel, LogValue, TimestampSource};
+use async_trait::async_trait;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+/// Fields checked, in order, for the `module` label
+const MODULE_FIELDS: [&str; 2] = ["module", "target"];
+
+/// Loki push configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LokiConfig {
+    /// Push endpoint; plain `http://` only
+    pub endpoint: String,
+
+    /// Sent as `X-Scope-OrgID` for multi-tenant Loki
+    pub tenant_id: Option<String>,
+
+    /// Gzip request bodies
+    pub gzip: bool,
+
+    /// Time each line is pushed with
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+
+    /// Attempts after the first for retryable failures
+    pub max_retries: u32,
+
+    /// Wait before the first retry, doubled for each one after, up to 5s
+    pub retry_backoff: Duration,
+
+    /// Limit for connecting, sending and reading the response, per attempt
+    pub timeout: Duration,
+}
+
+impl Default for LokiConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:3100/loki/api/v1/push".to_string(),
+            tenant_id: None,
+            gzip: true,
+            timestamp_source: TimestampSource::default(),
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(100),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Pushes batches to Grafana Loki
+pub struct LokiTransport {
+    endpoint: Endpoint,
+    retry: RetryPolicy,
+    tenant_id: Option<String>,
+    gzip: bool,
+    timestamp_source: TimestampSource,
+}
+
+impl LokiTransport {
+    pub fn new(config: LokiConfig) -> Result<Self> {
+        Ok(Self {
+            endpoint: Endpoint::parse(&config.endpoint, "/loki/api/v1/push")?,
+            retry: RetryPolicy {
+                max_retries: config.max_retries,
+                backoff: config.retry_backoff,
+                timeout: config.timeout,
+            },
+            tenant_id: config.tenant_id,
+            gzip: config.gzip,
+            timestamp_source: config.timestamp_source,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for LokiTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        let batch = &*crate::to_ndjson(batch)?;
+        let entries = serde_json::Deserializer::from_slice(batch)
+            .into_iter::<LogEntry>()
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut body = serde_json::to_vec(&push_request(&entries, self.timestamp_source)?)?;
+
+        let mut headers = vec![("Content-Type", "application/json")];
+        if self.gzip {
+            let mut encoder =
+                GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::fast());
+            encoder.write_all(&body)?;
+            body = encoder.finish()?;
+            headers.push(("Content-Encoding", "gzip"));
+        }
+        if let Some(tenant) = &self.tenant_id {
+            headers.push(("X-Scope-OrgID", tenant));
+        }
+
+        self.endpoint
+            .post_with_retry(self.endpoint.path(), &headers, &body, &self.retry)
+            .await
+            .map(drop)
+            .map_err(|reason| {
+                LoggerError::Transport(format!(
+                    "Loki push to {} failed: {}",
+                    self.endpoint.url(),
+                    reason
+                ))
+            })
+    }
+}
+
+/// Build a push request body with one stream per distinct label set
+fn push_request(entries: &[LogEntry], source: TimestampSource) -> Result<Value> {
+    let mut streams: BTreeMap<BTreeMap<&str, &str>, Vec<(i64, String)>> = BTreeMap::new();
+    for entry in entries {
+        let mut labels = BTreeMap::new();
+        labels.insert("service", &*entry.service);
+        labels.insert("level", level_label(entry.level));
+        let module = MODULE_FIELDS
+            .iter()
+            .find_map(|field| match entry.fields.get(*field) {
+                Some(LogValue::String(module)) => Some(module.as_str()),
+                _ => None,
+            });
+        if let Some(module) = module {
+            labels.insert("module", module);
+        }
+
+        let time = entry.time(source).timestamp_nanos_opt().unwrap_or_default();
+        streams
+            .entry(labels)
+            .or_default()
+            .push((time, serde_json::to_string(entry)?));
+    }
+
+    let streams: Vec<Value> = streams
+        .into_iter()
+        .map(|(labels, mut values)| {
+            // Loki rejects out-of-order entries within a stream unless the
+            // tenant allows them
+            values.sort_by_key(|(time, _)| *time);
+            let values: Vec<Value> = values
+                .into_iter()
+                .map(|(time, line)| json!([time.to_string(), line]))
+                .collect();
+            json!({ "stream": labels, "values": values })
+        })
+        .collect();
+
+    Ok(json!({ "streams": streams }))
+}
+
+/// Lowercase, as Grafana's level detection expects
+fn level_label(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Debug => "debug",
+        LogLevel::Info => "info",
+        LogLevel::Warn => "warn",
+        LogLevel::Error => "error",
+    }
+}
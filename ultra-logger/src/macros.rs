@@ -0,0 +1,108 @@
+//! Logging macros
+
+/// Log at a given level with `key = value` fields
+///
+/// Expands to a [`log_with_fields`](crate::UltraLogger::log_with_fields)
+/// future; `.await` it like any other log call. Values can be anything with
+/// a `From` conversion into [`LogValue`](crate::LogValue).
+#[macro_export]
+macro_rules! log_kv {
+    ($logger:expr, $level:expr, $message:expr $(, $key:ident = $value:expr)* $(,)?) => {
+        $logger.log_with_fields(
+            $level,
+            ::std::string::String::from($message),
+            &[$((stringify!($key), $crate::LogValue::from($value))),*],
+        )
+    };
+}
+
+/// Log at Debug level with `key = value` fields
+#[macro_export]
+macro_rules! debug_kv {
+    ($logger:expr, $($rest:tt)+) => {
+        $crate::log_kv!($logger, $crate::LogLevel::Debug, $($rest)+)
+    };
+}
+
+/// Log at Info level with `key = value` fields
+///
+/// ```
+/// use ultra_logger::{info_kv, Result, UltraLogger};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<()> {
+/// let logger = UltraLogger::new("order-gateway".to_string());
+/// info_kv!(logger, "order accepted", order_id = 42u64, symbol = "BTCUSD", price = 45000.5).await?;
+/// logger.shutdown().await
+/// # }
+/// ```
+#[macro_export]
+macro_rules! info_kv {
+    ($logger:expr, $($rest:tt)+) => {
+        $crate::log_kv!($logger, $crate::LogLevel::Info, $($rest)+)
+    };
+}
+
+/// Log at Warn level with `key = value` fields
+#[macro_export]
+macro_rules! warn_kv {
+    ($logger:expr, $($rest:tt)+) => {
+        $crate::log_kv!($logger, $crate::LogLevel::Warn, $($rest)+)
+    };
+}
+
+/// Log at Error level with `key = value` fields
+#[macro_export]
+macro_rules! error_kv {
+    ($logger:expr, $($rest:tt)+) => {
+        $crate::log_kv!($logger, $crate::LogLevel::Error, $($rest)+)
+    };
+}
+
+/// Log a `format!`-style message at Debug level through the global logger
+///
+/// Does nothing until [`init`](crate::init) has been called, and skips
+/// formatting when Debug is disabled. Never blocks inside a tokio runtime; see
+/// [`log_global`](crate::log_global).
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::log_global($crate::LogLevel::Debug, || ::std::format!($($arg)+))
+    };
+}
+
+/// Log a `format!`-style message at Info level through the global logger
+///
+/// Does nothing until [`init`](crate::init) has been called, and skips
+/// formatting when Info is disabled. Never blocks inside a tokio runtime; see
+/// [`log_global`](crate::log_global).
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::log_global($crate::LogLevel::Info, || ::std::format!($($arg)+))
+    };
+}
+
+/// Log a `format!`-style message at Warn level through the global logger
+///
+/// Does nothing until [`init`](crate::init) has been called, and skips
+/// formatting when Warn is disabled. Never blocks inside a tokio runtime; see
+/// [`log_global`](crate::log_global).
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        $crate::log_global($crate::LogLevel::Warn, || ::std::format!($($arg)+))
+    };
+}
+
+/// Log a `format!`-style message at Error level through the global logger
+///
+/// Does nothing until [`init`](crate::init) has been called, and skips
+/// formatting when Error is disabled. Never blocks inside a tokio runtime; see
+/// [`log_global`](crate::log_global).
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::log_global($crate::LogLevel::Error, || ::std::format!($($arg)+))
+    };
+}
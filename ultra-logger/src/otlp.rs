@@ -0,0 +1,183 @@
+//! OpenTelemetry log export over OTLP/HTTP with JSON encoding
+//!
+//! Each batch is re-read from NDJSON, grouped by service into one
+//! `ResourceLogs` per `service.name`, and POSTed to the collector's
+//! `/v1/logs` endpoint. `trace_id` and `span_id` fields become the record's
+//! trace context; every other field becomes an attribute.
+
+use crate::error::{LoggerError, Result};
+use crate::http::{Endpoint, RetryPolicy};
+use crate::transport::Transport;
+use crate::{LogEntry, LogLevel, LogValue, TimestampSource};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Field names lifted into the record's trace context
+const TRACE_ID_FIELD: &str = "trace_id";
+const SPAN_ID_FIELD: &str = "span_id";
+
+/// OTLP exporter configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OtlpConfig {
+    /// Collector logs endpoint; plain `http://` only
+    pub endpoint: String,
+
+    /// Attempts after the first for retryable failures
+    pub max_retries: u32,
+
+    /// Wait before the first retry, doubled for each one after, up to 5s
+    pub retry_backoff: Duration,
+
+    /// Limit for connecting, sending and reading the response, per attempt
+    pub timeout: Duration,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318/v1/logs".to_string(),
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(100),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Exports batches to an OpenTelemetry collector as OTLP log records
+pub struct OtlpTransport {
+    endpoint: Endpoint,
+    retry: RetryPolicy,
+}
+
+impl OtlpTransport {
+    pub fn new(config: OtlpConfig) -> Result<Self> {
+        Ok(Self {
+            endpoint: Endpoint::parse(&config.endpoint, "/v1/logs")?,
+            retry: RetryPolicy {
+                max_retries: config.max_retries,
+                backoff: config.retry_backoff,
+                timeout: config.timeout,
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for OtlpTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        let batch = &*crate::to_ndjson(batch)?;
+        let entries = serde_json::Deserializer::from_slice(batch)
+            .into_iter::<LogEntry>()
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let body = serde_json::to_vec(&export_request(&entries))?;
+
+        self.endpoint
+            .post_with_retry(
+                self.endpoint.path(),
+                &[("Content-Type", "application/json")],
+                &body,
+                &self.retry,
+            )
+            .await
+            .map(drop)
+            .map_err(|reason| {
+                LoggerError::Transport(format!(
+                    "OTLP export to {} failed: {}",
+                    self.endpoint.url(),
+                    reason
+                ))
+            })
+    }
+}
+
+/// Build an `ExportLogsServiceRequest` in OTLP/JSON form
+fn export_request(entries: &[LogEntry]) -> Value {
+    let mut by_service: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+    for entry in entries {
+        by_service
+            .entry(&*entry.service)
+            .or_default()
+            .push(log_record(entry));
+    }
+
+    let resource_logs: Vec<Value> = by_service
+        .into_iter()
+        .map(|(service, records)| {
+            json!({
+                "resource": {
+                    "attributes": [attribute("service.name", &LogValue::from(service))],
+                },
+                "scopeLogs": [{
+                    "scope": { "name": "ultra-logger", "version": env!("CARGO_PKG_VERSION") },
+                    "logRecords": records,
+                }],
+            })
+        })
+        .collect();
+
+    json!({ "resourceLogs": resource_logs })
+}
+
+fn log_record(entry: &LogEntry) -> Value {
+    let (severity_number, severity_text) = match entry.level {
+        LogLevel::Debug => (5, "DEBUG"),
+        LogLevel::Info => (9, "INFO"),
+        LogLevel::Warn => (13, "WARN"),
+        LogLevel::Error => (17, "ERROR"),
+    };
+    let nanos = |time: chrono::DateTime<chrono::Utc>| {
+        time.timestamp_nanos_opt().unwrap_or_default().to_string()
+    };
+
+    let mut attributes = Vec::with_capacity(entry.fields.len() + 2);
+    if let Some(event_id) = &entry.event_id {
+        attributes.push(attribute("event.id", &LogValue::from(event_id.as_str())));
+    }
+    if let Some(error_code) = &entry.error_code {
+        attributes.push(attribute(
+            "error.code",
+            &LogValue::from(error_code.as_str()),
+        ));
+    }
+    for (key, value) in &entry.fields {
+        if key != TRACE_ID_FIELD && key != SPAN_ID_FIELD {
+            attributes.push(attribute(key, value));
+        }
+    }
+
+    let mut record = json!({
+        "timeUnixNano": nanos(entry.time(TimestampSource::Event)),
+        "observedTimeUnixNano": nanos(entry.timestamp),
+        "severityNumber": severity_number,
+        "severityText": severity_text,
+        "body": { "stringValue": entry.message },
+        "attributes": attributes,
+    });
+    for (field, key) in [(TRACE_ID_FIELD, "traceId"), (SPAN_ID_FIELD, "spanId")] {
+        if let Some(LogValue::String(id)) = entry.fields.get(field) {
+            record[key] = Value::String(id.clone());
+        }
+    }
+    record
+}
+
+fn attribute(key: &str, value: &LogValue) -> Value {
+    let value = match value {
+        LogValue::Bool(b) => json!({ "boolValue": b }),
+        // OTLP/JSON carries 64-bit integers as strings
+        LogValue::I64(n) => json!({ "intValue": n.to_string() }),
+        LogValue::U64(n) => match i64::try_from(*n) {
+            Ok(n) => json!({ "intValue": n.to_string() }),
+            Err(_) => json!({ "stringValue": n.to_string() }),
+        },
+        LogValue::F64(f) => json!({ "doubleValue": f }),
+        LogValue::String(s) => json!({ "stringValue": s }),
+    };
+    json!({ "key": key, "value": value })
+}
@@ -0,0 +1,343 @@
+//! Isolated log partitions, e.g. one per trading strategy
+//!
+//! Each partition is a logger of its own, built from its own
+//! [`UltraLoggerBuilder`]: a separate bounded queue and backpressure policy,
+//! a separate background processor, transport, bandwidth budget and stats.
+//! Nothing is shared between partitions but the runtime, so one strategy's
+//! verbose research logging filling its queue or saturating its sink cannot
+//! hold up another's Error entries.
+//!
+//! Routes send entries carrying a given event ID or error code to a named
+//! partition whatever tag they were logged under, e.g. every risk-limit
+//! event to a partition whose transport feeds alerting.
+
+use crate::error::{LoggerError, Result};
+use crate::event::validate_code;
+use crate::{LogLevel, LoggerStats, UltraLogger, UltraLoggerBuilder};
+use std::collections::{BTreeMap, HashMap};
+
+/// Field stamped on every entry with the name of its partition
+pub const PARTITION_FIELD: &str = "partition";
+
+/// Partition used for tags without one of their own
+pub const DEFAULT_PARTITION: &str = "default";
+
+/// A set of independent loggers selected by tag
+///
+/// ```
+/// # fn main() -> ultra_logger::Result<()> {
+/// use ultra_logger::{BackpressurePolicy, BufferConfig, LogLevel, PartitionedLogger, UltraLogger};
+///
+/// let engine = PartitionedLogger::builder(UltraLogger::builder().with_service("engine"))
+///     .with_partition(
+///         "research",
+///         UltraLogger::builder()
+///             .with_service("research")
+///             .with_buffer_config(BufferConfig {
+///                 capacity: 4096,
+///                 backpressure: BackpressurePolicy::DropNewest,
+///                 ..Default::default()
+///             }),
+///     )
+///     .build()?;
+///
+/// engine
+///     .partition("research")
+///     .log_blocking(LogLevel::Debug, "signal recomputed".into(), &[])?;
+/// println!("{:?}", engine.stats()["research"]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct PartitionedLogger {
+    partitions: BTreeMap<String, UltraLogger>,
+    /// Partition for each routed code
+    routes: HashMap<String, String>,
+}
+
+/// Configures a [`PartitionedLogger`]
+pub struct PartitionedLoggerBuilder {
+    partitions: BTreeMap<String, UltraLoggerBuilder>,
+    routes: HashMap<String, String>,
+}
+
+impl PartitionedLogger {
+    /// Start with the default partition, which takes entries for any tag
+    /// without a partition of its own
+    pub fn builder(default: UltraLoggerBuilder) -> PartitionedLoggerBuilder {
+        PartitionedLoggerBuilder {
+            partitions: BTreeMap::from([(DEFAULT_PARTITION.to_string(), default)]),
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Logger for `tag`, or the default partition's when `tag` has none
+    pub fn partition(&self, tag: &str) -> &UltraLogger {
+        self.partitions
+            .get(tag)
+            .unwrap_or_else(|| &self.partitions[DEFAULT_PARTITION])
+    }
+
+    /// Logger for an entry with these codes logged under `tag`: the
+    /// partition routed to for the event ID, else for the error code, else
+    /// [`partition(tag)`](PartitionedLogger::partition)
+    pub fn route(
+        &self,
+        tag: &str,
+        event_id: Option<&str>,
+        error_code: Option<&str>,
+    ) -> &UltraLogger {
+        [event_id, error_code]
+            .into_iter()
+            .flatten()
+            .find_map(|code| self.routes.get(code))
+            .map_or_else(|| self.partition(tag), |routed| &self.partitions[routed])
+    }
+
+    /// [`UltraLogger::log_event`] on the partition `event_id` is routed to,
+    /// or `tag`'s
+    pub async fn log_event(
+        &self,
+        tag: &str,
+        level: LogLevel,
+        event_id: &str,
+        message: String,
+    ) -> Result<()> {
+        self.route(tag, Some(event_id), None)
+            .log_event(level, event_id, message)
+            .await
+    }
+
+    /// [`UltraLogger::error_with_code`] on the partition `error_code` is
+    /// routed to, or `tag`'s
+    pub async fn error_with_code(
+        &self,
+        tag: &str,
+        error_code: &str,
+        message: String,
+    ) -> Result<()> {
+        self.route(tag, None, Some(error_code))
+            .error_with_code(error_code, message)
+            .await
+    }
+
+    /// Partition names, including the default
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.partitions.keys().map(String::as_str)
+    }
+
+    /// Current counters of every partition
+    pub fn stats(&self) -> BTreeMap<String, LoggerStats> {
+        self.partitions
+            .iter()
+            .map(|(tag, logger)| (tag.clone(), logger.stats()))
+            .collect()
+    }
+
+    /// Flush every partition; the first error is returned after all have
+    /// been tried
+    pub async fn flush(&self) -> Result<()> {
+        let mut result = Ok(());
+        for logger in self.partitions.values() {
+            let flushed = logger.flush().await;
+            result = result.and(flushed);
+        }
+        result
+    }
+
+    /// Shut down every partition; the first error is returned after all
+    /// have been tried
+    pub async fn shutdown(&self) -> Result<()> {
+        let mut result = Ok(());
+        for logger in self.partitions.values() {
+            let shut_down = logger.shutdown().await;
+            result = result.and(shut_down);
+        }
+        result
+    }
+}
+
+impl PartitionedLoggerBuilder {
+    /// Give `tag` its own logger, replacing any earlier one for the tag
+    /// (including the default)
+    ///
+    /// Partitions should not share a file or socket path; each transport
+    /// assumes it is the only writer.
+    pub fn with_partition(mut self, tag: impl Into<String>, logger: UltraLoggerBuilder) -> Self {
+        self.partitions.insert(tag.into(), logger);
+        self
+    }
+
+    /// Send entries carrying `code`, as event ID or error code, to the
+    /// partition `tag`; fails on a malformed code
+    ///
+    /// Routing only picks the partition. The code still has to pass that
+    /// partition's [`EventRegistry`](crate::EventRegistry), if it has one.
+    pub fn with_route(mut self, code: impl Into<String>, tag: impl Into<String>) -> Result<Self> {
+        let code = code.into();
+        validate_code(&code)?;
+        self.routes.insert(code, tag.into());
+        Ok(self)
+    }
+
+    /// Start every partition's logger, stamping its entries with
+    /// [`PARTITION_FIELD`]; fails if a route names no partition
+    pub fn build(self) -> Result<PartitionedLogger> {
+        if let Some((code, tag)) = self
+            .routes
+            .iter()
+            .find(|(_, tag)| !self.partitions.contains_key(*tag))
+        {
+            return Err(LoggerError::Config(format!(
+                "route for {} names unknown partition {}",
+                code, tag
+            )));
+        }
+
+        let mut partitions = BTreeMap::new();
+        for (tag, builder) in self.partitions {
+            let logger = builder
+                .with_context(PARTITION_FIELD, tag.as_str())
+                .build()?;
+            partitions.insert(tag, logger);
+        }
+        Ok(PartitionedLogger {
+            partitions,
+            routes: self.routes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Discard, Gated};
+    use crate::{BackpressurePolicy, BufferConfig, LogEntry, LogLevel, LogValue};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn stalled_partition_does_not_hold_up_another() {
+        let (research, research_entered, research_gate, research_output) = Gated::new();
+        let (execution, _, execution_gate, execution_output) = Gated::new();
+        drop(execution_gate);
+        let engine = PartitionedLogger::builder(
+            UltraLogger::builder().with_custom_transport(Box::new(Discard)),
+        )
+        .with_partition(
+            "research",
+            UltraLogger::builder()
+                .with_buffer_config(BufferConfig {
+                    capacity: 4,
+                    backpressure: BackpressurePolicy::DropNewest,
+                    ..Default::default()
+                })
+                .with_custom_transport(Box::new(research)),
+        )
+        .with_partition(
+            "execution",
+            UltraLogger::builder().with_custom_transport(Box::new(execution)),
+        )
+        .build()
+        .unwrap();
+
+        // Research's sink is stuck on its first batch and its queue is full
+        let research = engine.partition("research");
+        research.info("stall".into()).await.unwrap();
+        research_entered.recv_async().await.unwrap();
+        for _ in 0..10 {
+            research.debug("signal recomputed".into()).await.unwrap();
+        }
+        assert!(engine.stats()["research"].dropped_newest > 0);
+
+        let execution = engine.partition("execution");
+        let delivered = tokio::time::timeout(Duration::from_secs(1), async {
+            execution
+                .error("position limit breached".into())
+                .await
+                .unwrap();
+            execution.flush().await.unwrap();
+        })
+        .await;
+        assert!(delivered.is_ok(), "execution partition held up by research");
+
+        let output = execution_output.lock().unwrap().clone();
+        let entry: LogEntry = serde_json::from_slice(&output).unwrap();
+        assert_eq!(entry.level, LogLevel::Error);
+        assert_eq!(entry.fields[PARTITION_FIELD], LogValue::from("execution"));
+        // Still stalled throughout
+        assert!(research_output.lock().unwrap().is_empty());
+
+        drop(research_gate);
+        engine.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn codes_are_routed_to_their_partition() {
+        let (alerts, _, alerts_gate, alerts_output) = Gated::new();
+        drop(alerts_gate);
+        let engine = PartitionedLogger::builder(
+            UltraLogger::builder().with_custom_transport(Box::new(Discard)),
+        )
+        .with_partition(
+            "alerts",
+            UltraLogger::builder().with_custom_transport(Box::new(alerts)),
+        )
+        .with_route("RISK-LIMIT", "alerts")
+        .unwrap()
+        .with_route("E1042", "alerts")
+        .unwrap()
+        .build()
+        .unwrap();
+
+        assert!(std::ptr::eq(
+            engine.route("mean-reversion", Some("ORD-FILL"), None),
+            engine.partition(DEFAULT_PARTITION)
+        ));
+        engine
+            .log_event(
+                "mean-reversion",
+                LogLevel::Warn,
+                "RISK-LIMIT",
+                "limit near".into(),
+            )
+            .await
+            .unwrap();
+        engine
+            .error_with_code("mean-reversion", "E1042", "order rejected".into())
+            .await
+            .unwrap();
+        engine
+            .log_event(
+                "mean-reversion",
+                LogLevel::Info,
+                "ORD-FILL",
+                "filled".into(),
+            )
+            .await
+            .unwrap();
+        engine.shutdown().await.unwrap();
+
+        let output = alerts_output.lock().unwrap().clone();
+        let routed: Vec<LogEntry> = serde_json::Deserializer::from_slice(&output)
+            .into_iter()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        let messages: Vec<&str> = routed.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!(messages, ["limit near", "order rejected"]);
+        assert_eq!(routed[0].event_id.as_deref(), Some("RISK-LIMIT"));
+        assert_eq!(routed[1].error_code.as_deref(), Some("E1042"));
+        assert_eq!(routed[0].fields[PARTITION_FIELD], LogValue::from("alerts"));
+    }
+
+    #[test]
+    fn routes_must_be_well_formed_and_name_a_partition() {
+        let builder = || PartitionedLogger::builder(UltraLogger::builder());
+        assert!(builder().with_route("RISK LIMIT", "alerts").is_err());
+        assert!(matches!(
+            builder()
+                .with_route("RISK-LIMIT", "alerts")
+                .unwrap()
+                .build(),
+            Err(LoggerError::Config(_))
+        ));
+    }
+}
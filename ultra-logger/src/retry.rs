@@ -0,0 +1,305 @@
+//! Redelivery of batches the transport failed to accept
+//!
+//! Every batch the processor writes gets a sequence number. With a
+//! [`RetryConfig`] a failed batch is parked here and resent with exponential
+//! backoff and jitter; one that runs out of attempts, or is pushed out of a
+//! full queue, goes to the dead-letter file. Each dead-lettered batch is a
+//! JSON header line followed by exactly `bytes` bytes of the batch as it was
+//! handed to the transport:
+//!
+//! ```text
+//! {"seq":42,"attempts":6,"entries":1000,"bytes":81234,"error":"..."}
+//! <batch bytes>
+//! ```
+
+use crate::config::RetryConfig;
+use crate::LogLevel;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::hash::BuildHasher;
+use std::io::{self, Write};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A batch waiting to be resent
+pub(crate) struct FailedBatch {
+    pub(crate) seq: u64,
+    pub(crate) bytes: Vec<u8>,
+    /// Level and event time of each entry, for delivery lag
+    pub(crate) entries: Vec<(LogLevel, DateTime<Utc>)>,
+    /// Sends tried so far, including the first
+    pub(crate) attempts: u32,
+    /// Transport error from the most recent send
+    pub(crate) error: String,
+    due: Instant,
+}
+
+pub(crate) struct RetryQueue {
+    config: RetryConfig,
+    pending: VecDeque<FailedBatch>,
+}
+
+impl RetryQueue {
+    pub(crate) fn new(config: RetryConfig) -> Self {
+        Self {
+            pending: VecDeque::with_capacity(config.queue_capacity),
+            config,
+        }
+    }
+
+    /// Park a batch whose first send failed with `error`; returns the oldest
+    /// parked batch if the queue was full, or this one if retries are off
+    pub(crate) fn push(
+        &mut self,
+        seq: u64,
+        bytes: Vec<u8>,
+        entries: Vec<(LogLevel, DateTime<Utc>)>,
+        error: String,
+    ) -> Option<FailedBatch> {
+        let batch = FailedBatch {
+            seq,
+            bytes,
+            entries,
+            attempts: 1,
+            error,
+            due: Instant::now() + self.backoff(1),
+        };
+        if self.config.max_retries == 0 {
+            return Some(batch);
+        }
+
+        let evicted = if self.pending.len() >= self.config.queue_capacity.max(1) {
+            self.pending.pop_front()
+        } else {
+            None
+        };
+        self.pending.push_back(batch);
+        evicted
+    }
+
+    /// Put back a batch whose resend failed with `error`; returns it when it
+    /// has no attempts left
+    pub(crate) fn reschedule(
+        &mut self,
+        mut batch: FailedBatch,
+        error: String,
+    ) -> Option<FailedBatch> {
+        batch.attempts += 1;
+        batch.error = error;
+        if batch.attempts > self.config.max_retries {
+            return Some(batch);
+        }
+        batch.due = Instant::now() + self.backoff(batch.attempts);
+        self.pending.push_back(batch);
+        None
+    }
+
+    /// When the earliest parked batch is due
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.pending.iter().map(|batch| batch.due).min()
+    }
+
+    /// Remove and return the batches that are due, or all of them, in
+    /// sequence order
+    pub(crate) fn take_due(&mut self, all: bool) -> Vec<FailedBatch> {
+        let now = Instant::now();
+        let (mut due, waiting): (Vec<_>, Vec<_>) = self
+            .pending
+            .drain(..)
+            .partition(|batch| all || batch.due <= now);
+        self.pending.extend(waiting);
+        due.sort_by_key(|batch| batch.seq);
+        due
+    }
+
+    /// Wait before retry number `attempts`: the initial backoff doubled per
+    /// earlier retry, capped, then randomised between half and all of it so
+    /// loggers that failed together don't retry in lockstep
+    fn backoff(&self, attempts: u32) -> Duration {
+        let doublings = attempts.saturating_sub(1).min(31);
+        let backoff = self
+            .config
+            .initial_backoff
+            .saturating_mul(1 << doublings)
+            .min(self.config.max_backoff);
+        let jitter = RandomState::new().hash_one(attempts) as f64 / u64::MAX as f64;
+        backoff.mul_f64(0.5 + jitter / 2.0)
+    }
+
+    /// Append a batch to the dead-letter file; `false` when none is configured
+    pub(crate) fn dead_letter(&self, batch: &FailedBatch, error: &str) -> io::Result<bool> {
+        let Some(path) = &self.config.dead_letter_path else {
+            return Ok(false);
+        };
+
+        let mut record = serde_json::to_vec(&json!({
+            "seq": batch.seq,
+            "attempts": batch.attempts,
+            "entries": batch.entries.len(),
+            "bytes": batch.bytes.len(),
+            "error": error,
+        }))?;
+        record.push(b'\n');
+        record.extend_from_slice(&batch.bytes);
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&record)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader, Read};
+
+    fn entries(count: usize) -> Vec<(LogLevel, DateTime<Utc>)> {
+        vec![(LogLevel::Info, Utc::now()); count]
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter() {
+        let retry = RetryQueue::new(RetryConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..Default::default()
+        });
+        for attempts in 1..=40 {
+            let full = Duration::from_millis(100)
+                .saturating_mul(1 << (attempts - 1).min(31))
+                .min(Duration::from_secs(1));
+            for _ in 0..50 {
+                let backoff = retry.backoff(attempts);
+                assert!(backoff >= full / 2, "{:?} below {:?}", backoff, full / 2);
+                assert!(backoff <= full, "{:?} above {:?}", backoff, full);
+            }
+        }
+    }
+
+    #[test]
+    fn batch_is_returned_once_retries_are_exhausted() {
+        let mut retry = RetryQueue::new(RetryConfig {
+            max_retries: 3,
+            ..Default::default()
+        });
+        assert!(retry
+            .push(7, b"batch".to_vec(), entries(1), "refused".into())
+            .is_none());
+
+        // The first send plus three resends
+        for attempts in 2..=3 {
+            let batch = retry.take_due(true).pop().unwrap();
+            assert!(
+                retry.reschedule(batch, "refused".into()).is_none(),
+                "attempt {}",
+                attempts
+            );
+        }
+        let batch = retry.take_due(true).pop().unwrap();
+        let exhausted = retry.reschedule(batch, "refused".into()).unwrap();
+        assert_eq!(exhausted.seq, 7);
+        assert_eq!(exhausted.attempts, 4);
+        assert!(retry.next_due().is_none());
+    }
+
+    #[test]
+    fn no_retries_returns_the_batch_straight_away() {
+        let mut retry = RetryQueue::new(RetryConfig {
+            max_retries: 0,
+            ..Default::default()
+        });
+        let batch = retry
+            .push(1, Vec::new(), entries(1), "refused".into())
+            .unwrap();
+        assert_eq!(batch.attempts, 1);
+        assert!(retry.next_due().is_none());
+    }
+
+    #[test]
+    fn full_queue_evicts_the_oldest_batch() {
+        let mut retry = RetryQueue::new(RetryConfig {
+            queue_capacity: 2,
+            initial_backoff: Duration::from_secs(3600),
+            max_backoff: Duration::from_secs(3600),
+            ..Default::default()
+        });
+        assert!(retry
+            .push(1, Vec::new(), entries(1), "refused".into())
+            .is_none());
+        assert!(retry
+            .push(2, Vec::new(), entries(1), "refused".into())
+            .is_none());
+        assert_eq!(
+            retry
+                .push(3, Vec::new(), entries(1), "refused".into())
+                .unwrap()
+                .seq,
+            1
+        );
+
+        // Nothing is due for an hour, but all of it can be taken at shutdown
+        assert!(retry.take_due(false).is_empty());
+        let seqs: Vec<u64> = retry.take_due(true).iter().map(|batch| batch.seq).collect();
+        assert_eq!(seqs, [2, 3]);
+    }
+
+    #[test]
+    fn dead_letter_is_a_header_then_exactly_the_batch_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead.letter");
+        let mut retry = RetryQueue::new(RetryConfig {
+            max_retries: 0,
+            dead_letter_path: Some(path.clone()),
+            ..Default::default()
+        });
+
+        // Batch bytes may contain newlines; the header's length frames them
+        let batches = [
+            &b"{\"a\":1}\n{\"a\":2}\n"[..],
+            &[0x28, 0xb5, 0x2f, 0xfd, b'\n'],
+        ];
+        for (seq, bytes) in batches.iter().enumerate() {
+            let batch = retry
+                .push(seq as u64, bytes.to_vec(), entries(2), "refused".into())
+                .unwrap();
+            assert!(retry.dead_letter(&batch, "connection refused").unwrap());
+        }
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        for (seq, bytes) in batches.iter().enumerate() {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header: serde_json::Value = serde_json::from_str(&header).unwrap();
+            assert_eq!(header["seq"], seq as u64);
+            assert_eq!(header["attempts"], 1);
+            assert_eq!(header["entries"], 2);
+            assert_eq!(header["error"], "connection refused");
+
+            let mut body = vec![0; header["bytes"].as_u64().unwrap() as usize];
+            reader.read_exact(&mut body).unwrap();
+            assert_eq!(&body, bytes);
+        }
+        assert_eq!(reader.read(&mut [0]).unwrap(), 0);
+    }
+
+    #[test]
+    fn dead_letter_without_a_path_is_a_no_op() {
+        let retry = RetryQueue::new(RetryConfig::default());
+        let batch = FailedBatch {
+            seq: 0,
+            bytes: Vec::new(),
+            entries: Vec::new(),
+            attempts: 1,
+            error: String::new(),
+            due: Instant::now(),
+        };
+        assert!(!retry.dead_letter(&batch, "error").unwrap());
+    }
+}
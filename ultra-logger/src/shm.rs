@@ -0,0 +1,386 @@
+//! Shared-memory ring buffer for handing batches to a same-host reader
+//!
+//! The ring lives in a memory-mapped file (normally under `/dev/shm`), laid
+//! out as a header followed by a power-of-two data region:
+//!
+//! | offset | contents                                   |
+//! |--------|--------------------------------------------|
+//! | 0      | magic (`u64`)                              |
+//! | 8      | data capacity in bytes (`u64`)             |
+//! | 64     | write position (`AtomicU64`, own line)     |
+//! | 128    | read position (`AtomicU64`, own line)      |
+//! | 192    | data                                       |
+//!
+//! Positions only grow; `write - read` bytes are in use. Each record is a
+//! little-endian `u32` length followed by the batch bytes, wrapping around
+//! the end of the data region. There is exactly one writer and one reader, so
+//! a release store of a position after copying is all the synchronisation
+//! needed, and neither side makes a syscall per record.
+
+use crate::error::{LoggerError, Result};
+use crate::transport::Transport;
+use async_trait::async_trait;
+use memmap2::{MmapMut, MmapOptions};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const MAGIC: u64 = 0x554c_5452_4152_4e47; // "ULTRARNG"
+const CAPACITY_OFFSET: usize = 8;
+const WRITE_POS_OFFSET: usize = 64;
+const READ_POS_OFFSET: usize = 128;
+const DATA_OFFSET: usize = 192;
+const LEN_PREFIX: usize = 4;
+
+/// How long the writer waits for the reader to make room before giving up on
+/// a batch
+const FULL_TIMEOUT: Duration = Duration::from_secs(1);
+const FULL_POLL: Duration = Duration::from_micros(50);
+
+/// Shared-memory ring configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShmRingConfig {
+    /// Backing file, created (and truncated) by the writer
+    pub path: PathBuf,
+
+    /// Data region size in bytes, rounded up to a power of two; the largest
+    /// batch that fits is this minus a 4-byte length prefix
+    pub capacity: usize,
+}
+
+impl Default for ShmRingConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("/dev/shm/ultra-logger"),
+            capacity: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Mapped ring shared by the writer and reader halves
+///
+/// The other process writes into the same bytes concurrently, so no Rust
+/// reference to the mapping is formed once it is set up: every access goes
+/// through `base`, taken from `map` once and valid for as long as `map` keeps
+/// the mapping alive.
+struct Ring {
+    _map: MmapMut,
+    base: *mut u8,
+    mask: u64,
+}
+
+// SAFETY: `base` points into the mapping owned by `_map`, which moves with the
+// ring; nothing about the mapping is tied to the thread that created it.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn create(config: &ShmRingConfig) -> std::io::Result<Self> {
+        let capacity = config.capacity.max(LEN_PREFIX + 1).next_power_of_two();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&config.path)?;
+        file.set_len((DATA_OFFSET + capacity) as u64)?;
+
+        // SAFETY: the file was just sized for the whole layout, and only the
+        // single writer and single reader touch it, via the atomics below.
+        let mut map = unsafe { MmapOptions::new().map_mut(&file)? };
+        map[CAPACITY_OFFSET..CAPACITY_OFFSET + 8].copy_from_slice(&(capacity as u64).to_le_bytes());
+        map[..8].copy_from_slice(&MAGIC.to_le_bytes());
+        map.flush()?;
+
+        Ok(Self::new(map, capacity as u64))
+    }
+
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: as in `create`; the header is validated before any offset
+        // beyond it is used.
+        let map = unsafe { MmapOptions::new().map_mut(&file)? };
+
+        let invalid =
+            || std::io::Error::new(std::io::ErrorKind::InvalidData, "not an ultra-logger ring");
+        if map.len() < DATA_OFFSET || u64::from_le_bytes(map[..8].try_into().unwrap()) != MAGIC {
+            return Err(invalid());
+        }
+        let capacity = u64::from_le_bytes(
+            map[CAPACITY_OFFSET..CAPACITY_OFFSET + 8]
+                .try_into()
+                .unwrap(),
+        );
+        if !capacity.is_power_of_two() || map.len() < DATA_OFFSET + capacity as usize {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(map, capacity))
+    }
+
+    fn new(mut map: MmapMut, capacity: u64) -> Self {
+        let base = map.as_mut_ptr();
+        Self {
+            _map: map,
+            base,
+            mask: capacity - 1,
+        }
+    }
+
+    fn capacity(&self) -> u64 {
+        self.mask + 1
+    }
+
+    fn position(&self, offset: usize) -> &AtomicU64 {
+        // SAFETY: both position offsets lie inside the mapping and are 8-byte
+        // aligned (mmap returns page-aligned memory). Each word is only ever
+        // accessed through `AtomicU64`, in this process and the other one,
+        // and `base` comes from `as_mut_ptr` rather than a shared borrow, so
+        // the stores through it are permitted while the other side writes.
+        unsafe { AtomicU64::from_ptr(self.base.add(offset).cast()) }
+    }
+
+    fn write_pos(&self) -> &AtomicU64 {
+        self.position(WRITE_POS_OFFSET)
+    }
+
+    fn read_pos(&self) -> &AtomicU64 {
+        self.position(READ_POS_OFFSET)
+    }
+
+    /// Copy `bytes` into the data region at ring position `pos`, wrapping
+    ///
+    /// Callers only write free space, which the reader does not look at until
+    /// the write position is published past it.
+    fn copy_in(&mut self, pos: u64, bytes: &[u8]) {
+        assert!(bytes.len() as u64 <= self.capacity());
+        let start = (pos & self.mask) as usize;
+        let first = bytes.len().min(self.capacity() as usize - start);
+        // SAFETY: both ranges lie inside the data region, since `start` is
+        // masked and the lengths are bounded by the capacity.
+        unsafe {
+            let data = self.base.add(DATA_OFFSET);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(start), first);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr().add(first), data, bytes.len() - first);
+        }
+    }
+
+    /// Copy from ring position `pos` into `out`, wrapping
+    ///
+    /// Callers only read published records, which the writer does not touch
+    /// again until the read position moves past them.
+    fn copy_out(&self, pos: u64, out: &mut [u8]) {
+        assert!(out.len() as u64 <= self.capacity());
+        let start = (pos & self.mask) as usize;
+        let first = out.len().min(self.capacity() as usize - start);
+        // SAFETY: as in `copy_in`.
+        unsafe {
+            let data = self.base.add(DATA_OFFSET);
+            std::ptr::copy_nonoverlapping(data.add(start), out.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(data, out.as_mut_ptr().add(first), out.len() - first);
+        }
+    }
+}
+
+/// Writes each batch as one record into a shared-memory ring
+///
+/// When the reader falls behind, the writer polls for up to a second for
+/// room, then fails the batch (counted as a transport error).
+pub struct ShmRingTransport {
+    ring: Ring,
+    path: PathBuf,
+}
+
+impl ShmRingTransport {
+    /// Create the backing file and initialise an empty ring
+    pub fn create(config: &ShmRingConfig) -> Result<Self> {
+        let ring = Ring::create(config).map_err(|e| {
+            LoggerError::Transport(format!(
+                "failed to create ring {}: {}",
+                config.path.display(),
+                e
+            ))
+        })?;
+        Ok(Self {
+            ring,
+            path: config.path.clone(),
+        })
+    }
+
+    /// Try to append one record; `false` means not enough free space yet
+    fn try_push(&mut self, batch: &[u8]) -> bool {
+        let needed = (LEN_PREFIX + batch.len()) as u64;
+        let write = self.ring.write_pos().load(Ordering::Relaxed);
+        let read = self.ring.read_pos().load(Ordering::Acquire);
+        if self.ring.capacity() - (write - read) < needed {
+            return false;
+        }
+
+        self.ring
+            .copy_in(write, &(batch.len() as u32).to_le_bytes());
+        self.ring.copy_in(write + LEN_PREFIX as u64, batch);
+        self.ring
+            .write_pos()
+            .store(write + needed, Ordering::Release);
+        true
+    }
+}
+
+#[async_trait]
+impl Transport for ShmRingTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        if (LEN_PREFIX + batch.len()) as u64 > self.ring.capacity()
+            || batch.len() > u32::MAX as usize
+        {
+            return Err(LoggerError::Transport(format!(
+                "batch of {} bytes does not fit ring {}",
+                batch.len(),
+                self.path.display()
+            )));
+        }
+
+        let deadline = tokio::time::Instant::now() + FULL_TIMEOUT;
+        while !self.try_push(batch) {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(LoggerError::Transport(format!(
+                    "ring {} full: reader not draining",
+                    self.path.display()
+                )));
+            }
+            tokio::time::sleep(FULL_POLL).await;
+        }
+        Ok(())
+    }
+}
+
+/// Reading half of a shared-memory ring, for the process draining it
+pub struct ShmRingReader {
+    ring: Ring,
+    path: PathBuf,
+}
+
+impl ShmRingReader {
+    /// Attach to a ring created by a [`ShmRingTransport`]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let ring = Ring::open(path).map_err(|e| {
+            LoggerError::Transport(format!("failed to open ring {}: {}", path.display(), e))
+        })?;
+        Ok(Self {
+            ring,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Pop the next batch into `out` (replacing its contents)
+    ///
+    /// Returns `false` without touching `out` when the ring is empty. The
+    /// positions and length prefix come from another process, so they are
+    /// checked against each other and the capacity; a ring that fails the
+    /// checks is an error and the read position is left where it was.
+    pub fn try_read(&mut self, out: &mut Vec<u8>) -> Result<bool> {
+        let read = self.ring.read_pos().load(Ordering::Relaxed);
+        let write = self.ring.write_pos().load(Ordering::Acquire);
+        if write == read {
+            return Ok(false);
+        }
+        let used = write.wrapping_sub(read);
+        if used < LEN_PREFIX as u64 || used > self.ring.capacity() {
+            return Err(self.corrupt(format!("positions {}..{}", read, write)));
+        }
+
+        let mut len = [0u8; LEN_PREFIX];
+        self.ring.copy_out(read, &mut len);
+        let len = u32::from_le_bytes(len) as usize;
+        if (LEN_PREFIX + len) as u64 > used {
+            return Err(self.corrupt(format!(
+                "record of {} bytes at {} but only {} written",
+                len,
+                read,
+                used - LEN_PREFIX as u64
+            )));
+        }
+
+        out.clear();
+        out.resize(len, 0);
+        self.ring.copy_out(read + LEN_PREFIX as u64, out);
+        self.ring
+            .read_pos()
+            .store(read + (LEN_PREFIX + len) as u64, Ordering::Release);
+        Ok(true)
+    }
+
+    fn corrupt(&self, detail: String) -> LoggerError {
+        LoggerError::Transport(format!("ring {} corrupt: {}", self.path.display(), detail))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(capacity: usize) -> (tempfile::TempDir, ShmRingTransport, ShmRingReader) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ShmRingConfig {
+            path: dir.path().join("ring"),
+            capacity,
+        };
+        let writer = ShmRingTransport::create(&config).unwrap();
+        let reader = ShmRingReader::open(&config.path).unwrap();
+        (dir, writer, reader)
+    }
+
+    #[test]
+    fn records_wrap_around_the_end() {
+        let (_dir, mut writer, mut reader) = ring(64);
+        let mut out = Vec::new();
+        // 4 + 20 bytes per record, so the start drifts across the 64-byte end
+        for i in 0..20u8 {
+            let batch = [i; 20];
+            assert!(writer.try_push(&batch));
+            assert!(reader.try_read(&mut out).unwrap());
+            assert_eq!(out, batch);
+        }
+        assert!(!reader.try_read(&mut out).unwrap());
+    }
+
+    #[test]
+    fn full_ring_refuses_until_read() {
+        let (_dir, mut writer, mut reader) = ring(64);
+        assert!(writer.try_push(&[1; 28]));
+        assert!(writer.try_push(&[2; 28]));
+        assert!(!writer.try_push(&[3; 1]));
+
+        let mut out = Vec::new();
+        assert!(reader.try_read(&mut out).unwrap());
+        assert_eq!(out, [1; 28]);
+        assert!(writer.try_push(&[3; 28]));
+        assert!(reader.try_read(&mut out).unwrap());
+        assert_eq!(out, [2; 28]);
+        assert!(reader.try_read(&mut out).unwrap());
+        assert_eq!(out, [3; 28]);
+    }
+
+    #[test]
+    fn corrupt_length_prefix_is_an_error() {
+        let (_dir, mut writer, mut reader) = ring(64);
+        assert!(writer.try_push(&[7; 8]));
+        writer.ring.copy_in(0, &100_000u32.to_le_bytes());
+
+        let mut out = Vec::new();
+        assert!(reader.try_read(&mut out).is_err());
+        // Left in place rather than skipped past
+        assert_eq!(reader.ring.read_pos().load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn corrupt_positions_are_an_error() {
+        let (_dir, writer, mut reader) = ring(64);
+        writer.ring.write_pos().store(1000, Ordering::Release);
+        assert!(reader.try_read(&mut Vec::new()).is_err());
+    }
+}
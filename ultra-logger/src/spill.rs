@@ -0,0 +1,278 @@
+//! Overflow file for the SpillToDisk backpressure policy
+
+use crate::LogEntry;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// What was found in a spill file left behind by a previous run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    /// Spill file that was scanned
+    pub path: PathBuf,
+
+    /// Intact entries kept for replay
+    pub entries_recovered: u64,
+
+    /// Byte ranges of complete lines that did not parse; skipped on replay
+    pub corrupted: Vec<Range<u64>>,
+
+    /// Bytes of an unterminated final line (a write cut short by a crash),
+    /// truncated away
+    pub truncated_bytes: u64,
+}
+
+impl RecoveryReport {
+    /// True when the file was empty or absent
+    pub fn is_empty(&self) -> bool {
+        self.entries_recovered == 0 && self.corrupted.is_empty() && self.truncated_bytes == 0
+    }
+
+    /// True when nothing had to be skipped or truncated
+    pub fn is_clean(&self) -> bool {
+        self.corrupted.is_empty() && self.truncated_bytes == 0
+    }
+}
+
+/// NDJSON file shared by producers (append) and the processor (drain)
+///
+/// The processor reads from a stored offset a chunk at a time, so replaying a
+/// large spill never holds more than one chunk in memory; the file is
+/// emptied once the offset catches up with the end.
+pub(crate) struct SpillFile {
+    file: Mutex<SpillState>,
+    /// Lines appended and not yet read back
+    pending: AtomicU64,
+}
+
+struct SpillState {
+    file: File,
+    /// Start of the first line not yet read back
+    read_offset: u64,
+}
+
+/// One chunk read back from the spill file
+#[derive(Debug, Default)]
+pub(crate) struct SpillChunk {
+    pub(crate) entries: Vec<LogEntry>,
+    /// Lines that did not parse and were passed over
+    pub(crate) skipped: u64,
+}
+
+impl SpillFile {
+    /// Open the spill file, keeping intact entries left from a previous run
+    /// for replay and truncating a torn final line
+    pub(crate) fn open(path: &Path) -> io::Result<(Self, RecoveryReport)> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        // Scanned a line at a time: after an outage the file can be far
+        // larger than memory
+        let mut report = RecoveryReport {
+            path: path.to_path_buf(),
+            ..RecoveryReport::default()
+        };
+        let mut reader = BufReader::new(&file);
+        let mut line = Vec::new();
+        let mut start = 0u64;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            if line.last() != Some(&b'\n') {
+                report.truncated_bytes = read;
+                break;
+            }
+            if serde_json::from_slice::<LogEntry>(&line).is_ok() {
+                report.entries_recovered += 1;
+            } else {
+                report.corrupted.push(start..start + read);
+            }
+            start += read;
+        }
+        drop(reader);
+        file.set_len(start)?;
+
+        let lines = report.entries_recovered + report.corrupted.len() as u64;
+        let spill = Self {
+            file: Mutex::new(SpillState {
+                file,
+                read_offset: 0,
+            }),
+            pending: AtomicU64::new(lines),
+        };
+        Ok((spill, report))
+    }
+
+    pub(crate) fn has_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire) > 0
+    }
+
+    /// Append one entry
+    pub(crate) fn push(&self, entry: &LogEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut state = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        state.file.write_all(&line)?;
+        self.pending.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Lines appended and not yet read back
+    pub(crate) fn pending(&self) -> u64 {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Read back up to `max` lines, emptying the file once all are read
+    pub(crate) fn take(&self, max: usize) -> io::Result<SpillChunk> {
+        let mut state = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let SpillState { file, read_offset } = &mut *state;
+        file.seek(SeekFrom::Start(*read_offset))?;
+
+        let mut chunk = SpillChunk::default();
+        let mut reader = BufReader::new(&*file);
+        let mut line = Vec::new();
+        let mut lines = 0;
+        while lines < max {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            // Appends hold the lock, so every line up to the end is complete
+            if read == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            *read_offset += read as u64;
+            lines += 1;
+            match serde_json::from_slice(&line) {
+                Ok(entry) => chunk.entries.push(entry),
+                Err(_) => chunk.skipped += 1,
+            }
+        }
+
+        if reader.fill_buf()?.is_empty() {
+            file.set_len(0)?;
+            *read_offset = 0;
+            self.pending.store(0, Ordering::Release);
+        } else {
+            self.pending.fetch_sub(lines as u64, Ordering::Release);
+        }
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogLevel;
+    use chrono::Utc;
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: Utc::now(),
+            level: LogLevel::Info,
+            service: "spill-test".into(),
+            message: message.into(),
+            event_time: None,
+            event_id: None,
+            error_code: None,
+            fields: Default::default(),
+        }
+    }
+
+    fn messages(entries: &[LogEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.message.as_str()).collect()
+    }
+
+    #[test]
+    fn take_reads_in_chunks_from_the_stored_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spill.ndjson");
+        let (spill, report) = SpillFile::open(&path).unwrap();
+        assert!(report.is_empty());
+
+        for i in 0..5 {
+            spill.push(&entry(&i.to_string())).unwrap();
+        }
+        let chunk = spill.take(2).unwrap();
+        assert_eq!(messages(&chunk.entries), ["0", "1"]);
+        assert_eq!(spill.pending(), 3);
+
+        // Appends land after the unread lines and are read in order
+        spill.push(&entry("5")).unwrap();
+        let chunk = spill.take(3).unwrap();
+        assert_eq!(messages(&chunk.entries), ["2", "3", "4"]);
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+
+        let chunk = spill.take(3).unwrap();
+        assert_eq!(messages(&chunk.entries), ["5"]);
+        assert!(!spill.has_pending());
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+        spill.push(&entry("6")).unwrap();
+        assert_eq!(messages(&spill.take(3).unwrap().entries), ["6"]);
+    }
+
+    #[test]
+    fn open_recovers_intact_lines_and_trims_a_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spill.ndjson");
+        let line = |message: &str| {
+            let mut line = serde_json::to_vec(&entry(message)).unwrap();
+            line.push(b'\n');
+            line
+        };
+        let bad = b"{\"timestamp\":\"garbled\n".to_vec();
+        let tail = &line("cut short")[..20];
+        let contents = [line("first"), bad.clone(), line("second"), tail.to_vec()].concat();
+        fs::write(&path, &contents).unwrap();
+
+        let (spill, report) = SpillFile::open(&path).unwrap();
+        let bad_start = line("first").len() as u64;
+        assert_eq!(report.path, path);
+        assert_eq!(report.entries_recovered, 2);
+        assert_eq!(report.corrupted.len(), 1);
+        assert_eq!(report.corrupted[0], bad_start..bad_start + bad.len() as u64);
+        assert_eq!(report.truncated_bytes, 20);
+        assert!(!report.is_clean());
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            (contents.len() - tail.len()) as u64
+        );
+
+        let chunk = spill.take(10).unwrap();
+        assert_eq!(messages(&chunk.entries), ["first", "second"]);
+        assert_eq!(chunk.skipped, 1);
+        assert!(!spill.has_pending());
+    }
+
+    #[test]
+    fn take_counts_lines_that_do_not_parse() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spill.ndjson");
+        let (spill, _) = SpillFile::open(&path).unwrap();
+
+        spill.push(&entry("good")).unwrap();
+        {
+            let mut state = spill.file.lock().unwrap();
+            state.file.write_all(b"{not json}\n").unwrap();
+        }
+        spill.pending.fetch_add(1, Ordering::Release);
+        spill.push(&entry("also good")).unwrap();
+
+        let chunk = spill.take(10).unwrap();
+        assert_eq!(messages(&chunk.entries), ["good", "also good"]);
+        assert_eq!(chunk.skipped, 1);
+        assert!(!spill.has_pending());
+    }
+}
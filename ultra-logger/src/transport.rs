@@ -0,0 +1,591 @@
+//! Transport layer: where serialized batches end up
+
+use crate::archive::{ArchiveConfig, ArchiveTransport};
+#[cfg(feature = "parquet")]
+use crate::columnar::{ParquetConfig, ParquetTransport};
+use crate::config::ConnectionConfig;
+use crate::elasticsearch::{ElasticsearchConfig, ElasticsearchTransport};
+use crate::error::{LoggerError, Result};
+use crate::loki::{LokiConfig, LokiTransport};
+use crate::otlp::{OtlpConfig, OtlpTransport};
+use crate::shm::{ShmRingConfig, ShmRingTransport};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use tokio::io::AsyncWriteExt;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// Destination for serialized log data
+///
+/// The background processor owns its transport exclusively and calls
+/// [`send_batch`](Transport::send_batch) once per flushed batch, so
+/// implementations don't need interior locking.
+///
+/// ```
+/// use async_trait::async_trait;
+/// use ultra_logger::{Result, Transport, UltraLogger};
+///
+/// struct ByteCounter(usize);
+///
+/// #[async_trait]
+/// impl Transport for ByteCounter {
+///     async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+///         self.0 += batch.len();
+///         Ok(())
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<()> {
+/// let logger = UltraLogger::new_with_transport("risk-engine".to_string(), Box::new(ByteCounter(0)));
+/// logger.info("limits loaded".to_string()).await?;
+/// logger.shutdown().await
+/// # }
+/// ```
+#[async_trait]
+pub trait Transport: Send {
+    /// Deliver a single serialized entry
+    async fn send(&mut self, entry: &[u8]) -> Result<()> {
+        self.send_batch(entry).await
+    }
+
+    /// Deliver a serialized batch (newline-delimited entries)
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()>;
+
+    /// Push any buffered data to its destination
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flush and release resources; called once when the logger shuts down
+    async fn shutdown(&mut self) -> Result<()> {
+        self.flush().await
+    }
+}
+
+/// Built-in transport selection
+#[derive(Debug, Clone, Default)]
+pub enum TransportKind {
+    /// Write NDJSON batches to standard output
+    #[default]
+    Stdout,
+
+    /// Write NDJSON batches to a size-rotated file
+    File(FileTransportConfig),
+
+    /// Stream NDJSON batches to a Unix domain socket on the same host
+    #[cfg(unix)]
+    UnixSocket(PathBuf),
+
+    /// Hand batches to a same-host reader through a shared-memory ring
+    SharedMemory(ShmRingConfig),
+
+    /// Export to an OpenTelemetry collector over OTLP/HTTP
+    Otlp(OtlpConfig),
+
+    /// Push to Grafana Loki
+    Loki(LokiConfig),
+
+    /// Index into Elasticsearch or OpenSearch through the `_bulk` API
+    Elasticsearch(ElasticsearchConfig),
+
+    /// Roll batches into zstd-compressed parts for long-term retention
+    Archive(ArchiveConfig),
+
+    /// Write Hive-partitioned Parquet files for analytics
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetConfig),
+}
+
+/// When the file transport forces data to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FsyncPolicy {
+    /// Leave it to the OS page cache
+    Never,
+
+    /// fsync after every batch (safest, slowest)
+    EveryBatch,
+
+    /// fsync only when a file is rotated out and on shutdown
+    OnRotate,
+}
+
+/// File transport configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTransportConfig {
+    /// Active log file; rotated files get a `.1`, `.2`, ... suffix
+    pub path: PathBuf,
+
+    /// Size in bytes after which the active file is rotated
+    pub max_file_size: u64,
+
+    /// Number of rotated files to keep (0 keeps none)
+    pub max_files: usize,
+
+    /// fsync behaviour
+    pub fsync_policy: FsyncPolicy,
+}
+
+impl FileTransportConfig {
+    /// Default settings writing to `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Settings from a `file` [`ConnectionConfig`]
+    ///
+    /// `host` is the log directory and the `file_name` option the file in it
+    /// (`ultra-logger.log` by default); a `path` option names the file
+    /// outright instead. `max_size` takes bytes or a `KB`/`MB`/`GB` size
+    /// (powers of 1024), `max_files` a count and `fsync` one of `never`,
+    /// `every_batch` or `on_rotate`. Any other option, such as time-based
+    /// `rotation`, is rejected rather than ignored.
+    pub(crate) fn from_connection(connection: &ConnectionConfig) -> Result<Self> {
+        let invalid = |key: &str, value: &str| {
+            LoggerError::Transport(format!("invalid file transport {}: {}", key, value))
+        };
+
+        let mut config = match connection.options.get("path") {
+            Some(path) => Self::new(path),
+            None => {
+                let name = connection
+                    .options
+                    .get("file_name")
+                    .map_or("ultra-logger.log", String::as_str);
+                Self::new(Path::new(&connection.host).join(name))
+            }
+        };
+        for (key, value) in &connection.options {
+            match key.as_str() {
+                "path" | "file_name" => {}
+                "max_size" => {
+                    config.max_file_size = parse_size(value)
+                        .filter(|&size| size > 0)
+                        .ok_or_else(|| invalid(key, value))?;
+                }
+                "max_files" => {
+                    config.max_files = value.parse().map_err(|_| invalid(key, value))?;
+                }
+                "fsync" => {
+                    config.fsync_policy = match value.as_str() {
+                        "never" => FsyncPolicy::Never,
+                        "every_batch" => FsyncPolicy::EveryBatch,
+                        "on_rotate" => FsyncPolicy::OnRotate,
+                        _ => return Err(invalid(key, value)),
+                    };
+                }
+                _ => {
+                    return Err(LoggerError::Transport(format!(
+                        "unsupported file transport option: {}",
+                        key
+                    )))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Bytes in a size such as `4096`, `512KB` or `100MB`
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" | "K" => 1024,
+        "MB" | "M" => 1024 * 1024,
+        "GB" | "G" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+impl Default for FileTransportConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("logs/ultra-logger.log"),
+            max_file_size: 100 * 1024 * 1024,
+            max_files: 5,
+            fsync_policy: FsyncPolicy::OnRotate,
+        }
+    }
+}
+
+impl TransportKind {
+    /// Open the selected transport
+    pub fn open(&self) -> Result<Box<dyn Transport>> {
+        match self {
+            TransportKind::Stdout => Ok(Box::new(StdoutTransport::new())),
+            TransportKind::File(config) => Ok(Box::new(FileTransport::open(config.clone())?)),
+            #[cfg(unix)]
+            TransportKind::UnixSocket(path) => Ok(Box::new(UnixSocketTransport::new(path))),
+            TransportKind::SharedMemory(config) => Ok(Box::new(ShmRingTransport::create(config)?)),
+            TransportKind::Otlp(config) => Ok(Box::new(OtlpTransport::new(config.clone())?)),
+            TransportKind::Loki(config) => Ok(Box::new(LokiTransport::new(config.clone())?)),
+            TransportKind::Elasticsearch(config) => {
+                Ok(Box::new(ElasticsearchTransport::new(config.clone())?))
+            }
+            TransportKind::Archive(config) => Ok(Box::new(ArchiveTransport::new(config.clone()))),
+            #[cfg(feature = "parquet")]
+            TransportKind::Parquet(config) => Ok(Box::new(ParquetTransport::new(config.clone())?)),
+        }
+    }
+}
+
+/// Writes batches to standard output
+pub struct StdoutTransport {
+    stdout: io::Stdout,
+}
+
+impl StdoutTransport {
+    /// Transport writing to this process's stdout
+    pub fn new() -> Self {
+        Self {
+            stdout: io::stdout(),
+        }
+    }
+}
+
+impl Default for StdoutTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Transport for StdoutTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        self.stdout.lock().write_all(batch)?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.stdout.lock().flush()?;
+        Ok(())
+    }
+}
+
+/// Append-only file with size-based rotation
+pub struct FileTransport {
+    config: FileTransportConfig,
+    file: File,
+    written: u64,
+}
+
+impl FileTransport {
+    /// Open (or create) the active file, creating parent directories as needed
+    pub fn open(config: FileTransportConfig) -> Result<Self> {
+        Self::open_file(config)
+            .map_err(|e| LoggerError::Transport(format!("failed to open log file: {}", e)))
+    }
+
+    fn open_file(config: FileTransportConfig) -> io::Result<Self> {
+        if let Some(parent) = config.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let file = Self::open_active(&config.path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            config,
+            file,
+            written,
+        })
+    }
+
+    fn open_active(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.config.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn write_batch(&mut self, batch: &[u8]) -> io::Result<()> {
+        // Batches are never split across files, so a file may overshoot
+        // max_file_size by at most one batch.
+        if self.written > 0 && self.written + batch.len() as u64 > self.config.max_file_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(batch)?;
+        self.written += batch.len() as u64;
+
+        if self.config.fsync_policy == FsyncPolicy::EveryBatch {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.config.fsync_policy != FsyncPolicy::Never {
+            self.file.sync_all()?;
+        }
+
+        if self.config.max_files == 0 {
+            fs::remove_file(&self.config.path)?;
+        } else {
+            let oldest = self.rotated_path(self.config.max_files);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for index in (1..self.config.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.config.path, self.rotated_path(1))?;
+        }
+
+        self.file = Self::open_active(&self.config.path)?;
+        self.written = 0;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.config.fsync_policy != FsyncPolicy::Never {
+            self.file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for FileTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        self.write_batch(batch)?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.sync()?;
+        Ok(())
+    }
+}
+
+/// Streams batches to a Unix domain socket, e.g. a same-host aggregator
+///
+/// Connects on first use and reconnects after a failed write, so the reader
+/// may restart without the logger being rebuilt. A batch is resent on the new
+/// connection only if none of it was written to the old one; one cut off
+/// partway fails instead, leaving a torn last line at the end of the old
+/// connection for the reader to discard rather than duplicating the entries
+/// before it.
+#[cfg(unix)]
+pub struct UnixSocketTransport {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixSocketTransport {
+    /// Transport for the socket at `path`; nothing is connected until the
+    /// first batch
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            stream: None,
+        }
+    }
+
+    /// Write `batch`, connecting first if needed; on failure the connection
+    /// is dropped and the error comes with the bytes already written
+    async fn write_batch(&mut self, batch: &[u8]) -> std::result::Result<(), (usize, io::Error)> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => {
+                let stream = UnixStream::connect(&self.path).await.map_err(|e| (0, e))?;
+                self.stream.insert(stream)
+            }
+        };
+        let mut written = 0;
+        while written < batch.len() {
+            let error = match stream.write(&batch[written..]).await {
+                Ok(0) => io::Error::from(io::ErrorKind::WriteZero),
+                Ok(n) => {
+                    written += n;
+                    continue;
+                }
+                Err(e) => e,
+            };
+            self.stream = None;
+            return Err((written, error));
+        }
+        Ok(())
+    }
+
+    fn error(&self, error: io::Error, written: usize, total: usize) -> LoggerError {
+        if written == 0 {
+            LoggerError::Transport(format!("unix socket {}: {}", self.path.display(), error))
+        } else {
+            LoggerError::Transport(format!(
+                "unix socket {}: {} after {} of {} bytes",
+                self.path.display(),
+                error,
+                written,
+                total
+            ))
+        }
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Transport for UnixSocketTransport {
+    async fn send_batch(&mut self, batch: &[u8]) -> Result<()> {
+        let (written, error) = match self.write_batch(batch).await {
+            Ok(()) => return Ok(()),
+            // A stale connection fails before taking any bytes; reconnect
+            Err((0, _)) => match self.write_batch(batch).await {
+                Ok(()) => return Ok(()),
+                Err(failed) => failed,
+            },
+            Err(failed) => failed,
+        };
+        Err(self.error(error, written, batch.len()))
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if let Some(stream) = &mut self.stream {
+            stream.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LoggerConfig, TransportConfig, UltraLogger};
+
+    fn file_connection(host: &Path, options: &[(&str, &str)]) -> ConnectionConfig {
+        ConnectionConfig {
+            host: host.display().to_string(),
+            options: options
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn file_connection_host_is_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let connection = file_connection(
+            dir.path(),
+            &[
+                ("file_name", "orders.log"),
+                ("max_size", "100MB"),
+                ("max_files", "3"),
+                ("fsync", "every_batch"),
+            ],
+        );
+        let config = FileTransportConfig::from_connection(&connection).unwrap();
+        assert_eq!(config.path, dir.path().join("orders.log"));
+        assert_eq!(config.max_file_size, 100 * 1024 * 1024);
+        assert_eq!(config.max_files, 3);
+        assert_eq!(config.fsync_policy, FsyncPolicy::EveryBatch);
+
+        let logger = UltraLogger::builder()
+            .with_config(&LoggerConfig {
+                transport: TransportConfig {
+                    transport_type: "file".to_string(),
+                    connection,
+                },
+                ..Default::default()
+            })
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(dir.path().join("orders.log").is_file());
+        drop(logger);
+    }
+
+    #[test]
+    fn file_connection_rejects_unsupported_options() {
+        let dir = tempfile::tempdir().unwrap();
+        for options in [
+            [("rotation", "daily")],
+            [("max_size", "100XB")],
+            [("max_size", "0")],
+            [("fsync", "sometimes")],
+        ] {
+            let connection = file_connection(dir.path(), &options);
+            assert!(FileTransportConfig::from_connection(&connection).is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_reconnects_when_reader_restarts() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ingest.sock");
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let mut transport = UnixSocketTransport::new(&path);
+
+        transport.send_batch(b"{\"n\":1}\n").await.unwrap();
+        let (mut first, _) = listener.accept().await.unwrap();
+        let mut received = [0u8; 8];
+        first.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"{\"n\":1}\n");
+        drop(first);
+
+        // Nothing reaches the closed connection, so the batch goes whole on
+        // a new one
+        transport.send_batch(b"{\"n\":2}\n").await.unwrap();
+        let (mut second, _) = listener.accept().await.unwrap();
+        drop(transport);
+        let mut received = Vec::new();
+        second.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"{\"n\":2}\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_works_without_a_runtime() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ingest.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let logger = UltraLogger::builder()
+            .with_transport(TransportKind::UnixSocket(path))
+            .build()
+            .unwrap();
+        logger
+            .log_blocking(crate::LogLevel::Info, "no runtime".into(), &[])
+            .unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        drop(logger);
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert!(received.contains("no runtime"));
+    }
+
+    #[test]
+    fn sizes_parse_with_binary_units() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("512KB"), Some(512 * 1024));
+        assert_eq!(parse_size("2 gb"), Some(2 << 30));
+        assert_eq!(parse_size("MB"), None);
+    }
+}